@@ -2,7 +2,7 @@ use super::candles::Candle;
 use crate::{clock, config};
 use std::{fs, io::Write, time::SystemTime};
 
-pub fn client(env: &config::Env) -> Client {
+pub fn client(env: &config::Env) -> Client<'_> {
     Client {
         api_key: &env["ALPHA_VANTAGE_KEY"],
         base_url: "https://www.alphavantage.co/query",
@@ -68,6 +68,33 @@ impl<'a> Client<'a> {
             None
         }
     }
+
+    // The day's latest 1 minute candles. They're never cached, since trading
+    // while the market is open needs each new one.
+    pub fn latest_candles(&self, ticker: &String) -> Option<Vec<Candle>> {
+        let function = "TIME_SERIES_INTRADAY".to_string();
+        let interval = "1min".to_string();
+        let datatype = "csv".to_string();
+        let params = vec![
+            ("apiKey", self.api_key),
+            ("symbol", ticker),
+            ("function", &function),
+            ("interval", &interval),
+            ("datatype", &datatype),
+        ];
+        let response = super::get(self.base_url, String::new(), &params);
+        let response_status = response.status_text().to_string();
+
+        if let Ok(csv) = response.into_string() {
+            csv_to_candles(csv)
+        } else {
+            eprintln!(
+                "\nAlphaVantage.latest_candles response error: {:?}",
+                response_status
+            );
+            None
+        }
+    }
 }
 
 fn cache_results(ticker: &str, results: &String) {
@@ -80,7 +107,7 @@ fn cache_results(ticker: &str, results: &String) {
     path.set_extension("csv");
     let mut file = fs::File::create(path).expect("Error creating cache file!");
 
-    file.write(results.as_bytes())
+    file.write_all(results.as_bytes())
         .expect("Error writing candle to file!");
 }
 
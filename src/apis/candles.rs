@@ -1,6 +1,7 @@
 use crate::clock;
 use std::fmt;
 
+#[derive(Clone)]
 pub struct Candle {
     pub open: f64,
     pub close: f64,
@@ -38,6 +39,14 @@ impl Candle {
     }
 }
 
+// Test candles take Candle::new's arguments, stamped minute minutes after the
+// 9:30 open on 2020-09-29.
+#[cfg(test)]
+pub fn candle(open: f64, close: f64, high: f64, low: f64, volume: i64, minute: i64) -> Candle {
+    let time = clock::datetime(2020, 9, 29, 9, 30, 0) + clock::Duration::minutes(minute);
+    Candle::new(open, close, high, low, volume, time)
+}
+
 impl fmt::Display for Candle {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let datetime = self.datetime.format("%D %l:%M:%S %p %z").to_string();
@@ -1,5 +1,7 @@
 pub mod alpha_vantage;
 pub mod candles;
+// an alternative data source, not currently selected anywhere
+#[allow(dead_code)]
 pub mod polygon;
 // pub mod td_ameritrade;
 
@@ -8,7 +8,7 @@ use std::{
     time::SystemTime,
 };
 
-pub fn client(env: &config::Env) -> Client {
+pub fn client(env: &config::Env) -> Client<'_> {
     Client {
         api_key: &env["POLYGON_API_KEY"],
         base_url: "https://api.polygon.io/v2",
@@ -89,7 +89,7 @@ fn cache_results(ticker: &str, results: &Vec<serde_json::value::Value>) {
     let mut file = fs::File::create(path).expect("Error creating cache file!");
 
     let json = json!({ "candles": results });
-    file.write(serde_json::to_string(&json).unwrap().as_bytes())
+    file.write_all(serde_json::to_string(&json).unwrap().as_bytes())
         .expect("Error writing candle to file!");
 }
 
@@ -1,13 +1,34 @@
 use super::{
     apis::alpha_vantage,
-    clock, config, strategies,
-    trading::{Account, Broker, PriceData},
+    benchmark, clock, config,
+    declarative::{DeclarativeStrategy, StrategyDefinition},
+    engine::{self, EngineConfig},
+    exits::{ExitPolicy, Exits},
+    metrics,
+    montecarlo::{self, MonteCarloConfig},
+    output,
+    pdt::DayTrades,
+    report::Report,
+    risk::{RiskLimits, RiskManager},
+    scripting::ScriptStrategy,
+    sizing::SizingPolicy,
+    strategies::{self, Builtin},
+    trading::{self, Account, Broker, Holding, PairPriceData, PriceData},
 };
+use std::path::Path;
+
+pub const CAPITAL: f64 = 1000.0;
 
 pub struct BacktestBroker {
     capital: f64,
 }
 
+impl BacktestBroker {
+    pub fn new(capital: f64) -> Self {
+        Self { capital }
+    }
+}
+
 impl Broker for BacktestBroker {
     fn capital(&mut self, _time: clock::LocalDateTime) -> f64 {
         self.capital
@@ -17,6 +38,11 @@ impl Broker for BacktestBroker {
         0.0
     }
 
+    // every trade is sized off the same capital
+    fn equity(&mut self, _time: clock::LocalDateTime, _open_value: f64) -> f64 {
+        self.capital
+    }
+
     fn is_market_open(&self, datetime: clock::LocalDateTime) -> bool {
         let open = clock::Time::from_hms(9, 30, 0);
         let close = clock::Time::from_hms(16, 0, 0);
@@ -27,7 +53,7 @@ impl Broker for BacktestBroker {
 
     fn sell_order(
         &mut self,
-        _ticker: &String,
+        _ticker: &str,
         _shares: i32,
         _price: f64,
         _time: clock::LocalDateTime,
@@ -36,7 +62,17 @@ impl Broker for BacktestBroker {
 
     fn buy_order(
         &mut self,
-        _ticker: &String,
+        _ticker: &str,
+        _shares: i32,
+        _price: f64,
+        _time: clock::LocalDateTime,
+    ) -> Option<f64> {
+        Some(self.capital)
+    }
+
+    fn short_order(
+        &mut self,
+        _ticker: &str,
         _shares: i32,
         _price: f64,
         _time: clock::LocalDateTime,
@@ -45,20 +81,224 @@ impl Broker for BacktestBroker {
     }
 }
 
-pub fn run_backtest(tickers: &[String], env: &config::Env, verbose: bool) {
+pub enum StrategySource {
+    Builtin(strategies::Builtin),
+    Definition(Box<StrategyDefinition>),
+    Script(String),
+}
+
+// How every backtest account holds, exits, sizes and limits its positions.
+#[derive(Default)]
+pub struct AccountSettings {
+    pub holding: Holding,
+    pub exits: ExitPolicy,
+    // strategy definitions carry their own sizing
+    pub sizing: SizingPolicy,
+    pub risk: RiskLimits,
+    // refuse a fourth day trade in five business days under $25,000
+    pub pattern_day_trader_rule: bool,
+    // how strategy orders are filled
+    pub engine: EngineConfig,
+}
+
+impl AccountSettings {
+    pub fn account(&self) -> Account<'static, BacktestBroker> {
+        let mut account = Account::new(BacktestBroker::new(CAPITAL));
+        account.holding = self.holding;
+        account.exits = Exits::new(self.exits);
+        account.sizing = self.sizing.clone();
+        account.risk = RiskManager::new(self.risk.clone());
+        if self.pattern_day_trader_rule {
+            account.day_trades = DayTrades::enforced();
+        }
+        account.fill_at = self.engine.fill_at;
+        account
+    }
+
+    // Swing positions trade daily bars, which only go back as far as the price
+    // history and which the opening range and gap strategies have no use for.
+    pub fn check_builtin(&self, builtin: Builtin) -> Result<(), String> {
+        if let Holding::Swing(_) = self.holding {
+            match builtin.swing_history() {
+                None => return Err(
+                    "the opening range and gap strategies only trade intraday, not with --swing"
+                        .to_string(),
+                ),
+                Some(bars) if bars >= trading::HISTORY_DAILY_BARS => {
+                    return Err(format!(
+                        "the strategy needs {} daily bars of history with --swing, more than \
+                         the {} there are",
+                        bars,
+                        trading::HISTORY_DAILY_BARS
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Analysis printed after each ticker's results.
+#[derive(Default)]
+pub struct Reports {
+    pub monte_carlo: Option<MonteCarloConfig>,
+    // an index to compare against along with buying and holding the ticker
+    pub benchmark: Option<String>,
+    // write trades, equity and metrics files
+    pub output: Option<output::Format>,
+    // write one HTML page of charts for the run
+    pub html: bool,
+}
+
+impl Reports {
+    // The reports follow one ticker's prices, which a pair's positions don't.
+    pub fn check_pairs(&self) -> Result<(), String> {
+        if self.monte_carlo.is_some()
+            || self.benchmark.is_some()
+            || self.output.is_some()
+            || self.html
+        {
+            Err(
+                "--monte-carlo, --benchmark, --output and --html can't be used with --pairs"
+                    .to_string(),
+            )
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn run_backtest(
+    tickers: &[String],
+    env: &config::Env,
+    verbose: bool,
+    source: &StrategySource,
+    settings: &AccountSettings,
+    reports: &Reports,
+) {
+    if let StrategySource::Definition(definition) = source {
+        println!("Strategy: {}", definition.name);
+    }
+    let index = reports.benchmark.as_ref().and_then(|symbol| {
+        let candles = trading::fetch_candles(&alpha_vantage::client(env), symbol, "1:minute");
+        if candles.is_none() {
+            eprintln!("{}: no price data", symbol);
+        }
+        candles.map(|candles| (symbol.as_str(), candles))
+    });
+
+    let mut report = Report::default();
     for ticker in tickers {
-        let mut account = Account::new(BacktestBroker { capital: 1000.0 });
-        let mut price_data = PriceData::new(alpha_vantage::client(&env));
+        let mut account = settings.account();
+        let mut price_data = PriceData::new(alpha_vantage::client(env));
+        let history = match (source, settings.holding) {
+            (StrategySource::Definition(definition), Holding::Intraday) => {
+                price_data.history(ticker, definition.history, "1:minute")
+            }
+            (StrategySource::Definition(definition), Holding::Swing(_)) => {
+                price_data.daily_history(ticker, definition.history)
+            }
+            (StrategySource::Builtin(builtin), Holding::Swing(_)) => builtin
+                .swing_history()
+                .and_then(|bars| price_data.daily_history(ticker, bars)),
+            (_, Holding::Intraday) => price_data.history(ticker, 180, "1:minute"),
+            (StrategySource::Script(_), Holding::Swing(_)) => price_data.daily_history(ticker, 9),
+        };
 
-        if let Some(candles) = price_data.history(ticker, 180, "1:minute") {
-            let mut strategy = strategies::SmaCrossover::new(ticker, candles);
-            // let mut strategy = strategies::Sma9CrossesSma180::new(ticker, candles);
-            strategy.execute(&mut price_data, &mut account);
+        if let Some(candles) = history {
+            let result = match source {
+                StrategySource::Builtin(builtin) => {
+                    let mut strategy = builtin.strategy(candles);
+                    engine::run_strategy(
+                        ticker,
+                        strategy.as_mut(),
+                        &mut price_data,
+                        &mut account,
+                        settings.engine,
+                    );
+                    Ok(())
+                }
+                StrategySource::Definition(definition) => {
+                    DeclarativeStrategy::new(definition, ticker, candles)
+                        .map(|mut strategy| strategy.execute(&mut price_data, &mut account))
+                }
+                StrategySource::Script(path) => ScriptStrategy::load(path, ticker, candles)
+                    .and_then(|mut strategy| strategy.execute(&mut price_data, &mut account)),
+            };
+
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                break;
+            }
+            if let Some(config) = &reports.monte_carlo {
+                let returns = metrics::closed_returns(&account.positions);
+                montecarlo::log_results(ticker, &returns, config);
+            }
+            if reports.benchmark.is_some() {
+                benchmark::log_results(
+                    ticker,
+                    &account.positions,
+                    price_data.traded_candles(),
+                    index
+                        .as_ref()
+                        .map(|(symbol, candles)| (*symbol, candles.as_slice())),
+                    CAPITAL,
+                );
+            }
+            if let Some(format) = reports.output {
+                let candles = price_data.traded_candles();
+                if let Err(err) =
+                    output::write(ticker, &account.positions, candles, CAPITAL, format)
+                {
+                    eprintln!("{}", err);
+                }
+            }
+            if reports.html {
+                report.add(
+                    ticker,
+                    &account.positions,
+                    price_data.traded_candles(),
+                    CAPITAL,
+                );
+            }
             log_results(ticker, account, verbose);
         } else {
             break;
         }
     }
+
+    if reports.html {
+        let path = Path::new(output::DIRECTORY).join("report.html");
+        match report.write(&path) {
+            Ok(()) => println!("Report written to {}", path.display()),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+}
+
+// Backtests PairsTrade on the first two tickers.
+pub fn run_pairs_backtest(
+    tickers: &[String],
+    env: &config::Env,
+    verbose: bool,
+    settings: &AccountSettings,
+) {
+    if tickers.len() < 2 {
+        eprintln!("Pairs trading needs two tickers");
+        return;
+    }
+
+    let config = strategies::PairsConfig::default();
+    let mut account = settings.account();
+    let mut price_data = PairPriceData::new(alpha_vantage::client(env));
+    let tickers = (&tickers[0], &tickers[1]);
+
+    if let Some(pairs) = price_data.history(tickers, config.bars, "1:minute") {
+        let mut strategy = strategies::PairsTrade::new(tickers, pairs, config);
+        strategy.execute(&mut price_data, &mut account);
+        log_results(&format!("{}/{}", tickers.0, tickers.1), account, verbose);
+    }
 }
 
 fn log_results(ticker: &String, account: Account<BacktestBroker>, verbose: bool) {
@@ -81,6 +321,47 @@ fn log_results(ticker: &String, account: Account<BacktestBroker>, verbose: bool)
         for position in &account.positions {
             println!("Position {}", position);
         }
+        for rejection in &account.risk.rejections {
+            println!("Rejected {:?}", rejection);
+        }
+        for flag in &account.day_trades.flags {
+            println!("PDT {}", flag);
+        }
+    }
+    for time in &account.margin_calls {
+        println!("Margin call {} - liquidated", time);
+    }
+    for gap in &account.suspect_gaps {
+        println!("Suspect gap {}", gap);
+    }
+    if let Some(halt) = &account.risk.halted {
+        println!("Halted {}", halt);
+    }
+    let excursions = metrics::Excursions::new(&account.positions);
+    if !account.positions.is_empty() {
+        let edge_ratio = match excursions.edge_ratio {
+            Some(ratio) => format!("{:.2}", ratio),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:6}-- MAE/MFE: ${:.4}/${:.4} - Edge ratio: {}",
+            ticker, excursions.average_mae, excursions.average_mfe, edge_ratio
+        );
+    }
+    if !account.risk.rejections.is_empty() {
+        println!(
+            "{:6}-- {} orders rejected",
+            ticker,
+            account.risk.rejections.len()
+        );
+    }
+    if !account.day_trades.flags.is_empty() {
+        println!(
+            "{:6}-- {} day trades, {} PDT flags",
+            ticker,
+            account.day_trades.total(),
+            account.day_trades.flags.len()
+        );
     }
 
     println!(
@@ -96,4 +377,38 @@ fn log_results(ticker: &String, account: Account<BacktestBroker>, verbose: bool)
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::{AccountSettings, Reports};
+    use crate::{
+        strategies::{Builtin, GapSetup, OrbStop},
+        trading::{Holding, SwingConfig},
+    };
+
+    #[test]
+    fn swing_backtests_reject_builtins_without_daily_history() {
+        let intraday = AccountSettings::default();
+        let swing = AccountSettings {
+            holding: Holding::Swing(SwingConfig::default()),
+            ..AccountSettings::default()
+        };
+        let orb = Builtin::OpeningRangeBreakout(OrbStop::Midpoint);
+        assert!(intraday.check_builtin(orb).is_ok());
+        assert!(swing.check_builtin(orb).is_err());
+        assert!(swing
+            .check_builtin(Builtin::Gap(GapSetup::GapFade))
+            .is_err());
+        assert!(swing.check_builtin(Builtin::Sma9CrossesSma180).is_err());
+        assert!(swing.check_builtin(Builtin::MeanReversion).is_ok());
+        assert!(swing.check_builtin(Builtin::SmaCrossover).is_ok());
+    }
+
+    #[test]
+    fn pairs_backtests_reject_reports() {
+        assert!(Reports::default().check_pairs().is_ok());
+        let html = Reports {
+            html: true,
+            ..Reports::default()
+        };
+        assert!(html.check_pairs().is_err());
+    }
+}
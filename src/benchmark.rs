@@ -0,0 +1,264 @@
+use super::{apis::candles::Candle, clock, trading::Position};
+
+const TRADING_DAYS: f64 = 252.0;
+
+// Buying and holding a symbol from the open of the first candle, valued at
+// each day's last close.
+pub struct Benchmark {
+    pub name: String,
+    start: f64,
+    closes: Vec<(clock::LocalDate, f64)>,
+}
+
+impl Benchmark {
+    // None without any candles.
+    pub fn new(name: &str, candles: &[Candle]) -> Option<Self> {
+        let start = candles.first()?.open;
+        let mut closes: Vec<(clock::LocalDate, f64)> = Vec::new();
+        for candle in candles {
+            let date = candle.datetime.date();
+            match closes.last_mut() {
+                Some(last) if last.0 == date => last.1 = candle.close,
+                _ => closes.push((date, candle.close)),
+            }
+        }
+        Some(Self {
+            name: name.to_string(),
+            start,
+            closes,
+        })
+    }
+
+    // Only the candles from the first to the last of dates, e.g. an index
+    // over the days a ticker was traded.
+    pub fn between(name: &str, candles: &[Candle], dates: &[clock::LocalDate]) -> Option<Self> {
+        let (first, last) = (dates.first()?, dates.last()?);
+        let from = candles.partition_point(|c| c.datetime.date() < *first);
+        let to = candles.partition_point(|c| c.datetime.date() <= *last);
+        Self::new(name, &candles[from..to])
+    }
+
+    pub fn dates(&self) -> Vec<clock::LocalDate> {
+        self.closes.iter().map(|(date, _)| *date).collect()
+    }
+
+    pub fn total_return(&self) -> f64 {
+        match self.closes.last() {
+            Some((_, close)) => close / self.start - 1.0,
+            None => 0.0,
+        }
+    }
+
+    // Close to close returns on each of dates, zero on days without a close.
+    pub fn returns(&self, dates: &[clock::LocalDate]) -> Vec<f64> {
+        dates
+            .iter()
+            .map(|date| {
+                let index = self.closes.partition_point(|(day, _)| day < date);
+                match self.closes.get(index) {
+                    Some((day, close)) if day == date => {
+                        let previous = match index {
+                            0 => self.start,
+                            _ => self.closes[index - 1].1,
+                        };
+                        close / previous - 1.0
+                    }
+                    _ => 0.0,
+                }
+            })
+            .collect()
+    }
+}
+
+// Profit of the positions closed on each of dates as a fraction of capital.
+// Positions count on the day they close, so returns of swing trades are
+// lumped into their last day.
+pub fn daily_returns(positions: &[Position], dates: &[clock::LocalDate], capital: f64) -> Vec<f64> {
+    dates
+        .iter()
+        .map(|date| {
+            positions
+                .iter()
+                .filter(|p| !p.open && p.closes.last().map(|c| c.time.date()) == Some(*date))
+                .map(|p| p.total_return())
+                .sum::<f64>()
+                / capital
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Comparison {
+    pub name: String,
+    pub benchmark_return: f64,
+    // strategy return less the benchmark's over the whole run
+    pub excess_return: f64,
+    // annualized return not explained by the benchmark
+    pub alpha: f64,
+    pub beta: f64,
+    pub correlation: f64,
+    // annualized excess return per unit of tracking error, None if the
+    // strategy tracks the benchmark exactly
+    pub information_ratio: Option<f64>,
+}
+
+pub fn compare(
+    benchmark: &Benchmark,
+    strategy_return: f64,
+    strategy: &[f64],
+    dates: &[clock::LocalDate],
+) -> Comparison {
+    let returns = benchmark.returns(dates);
+    let benchmark_variance = covariance(&returns, &returns);
+    let strategy_variance = covariance(strategy, strategy);
+    let both = covariance(strategy, &returns);
+
+    let beta = if benchmark_variance > 0.0 {
+        both / benchmark_variance
+    } else {
+        0.0
+    };
+    let correlation = if benchmark_variance > 0.0 && strategy_variance > 0.0 {
+        both / (benchmark_variance * strategy_variance).sqrt()
+    } else {
+        0.0
+    };
+    let alpha = (mean(strategy) - beta * mean(&returns)) * TRADING_DAYS;
+
+    let excess: Vec<f64> = strategy.iter().zip(&returns).map(|(s, b)| s - b).collect();
+    let tracking_error = covariance(&excess, &excess).sqrt();
+    let information_ratio = if tracking_error > 0.0 {
+        Some(mean(&excess) / tracking_error * TRADING_DAYS.sqrt())
+    } else {
+        None
+    };
+
+    Comparison {
+        name: benchmark.name.clone(),
+        benchmark_return: benchmark.total_return(),
+        excess_return: strategy_return - benchmark.total_return(),
+        alpha,
+        beta,
+        correlation,
+        information_ratio,
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+// Population covariance of two series of the same length.
+fn covariance(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - mean_a) * (y - mean_b))
+        .sum::<f64>()
+        / a.len() as f64
+}
+
+// Compares a backtest of ticker against buying and holding it, and the index
+// when there is one.
+pub fn log_results(
+    ticker: &str,
+    positions: &[Position],
+    candles: &[Candle],
+    index: Option<(&str, &[Candle])>,
+    capital: f64,
+) {
+    let hold = match Benchmark::new(ticker, candles) {
+        Some(hold) => hold,
+        None => return,
+    };
+    let dates = hold.dates();
+    let strategy = daily_returns(positions, &dates, capital);
+    let strategy_return = strategy.iter().sum::<f64>();
+
+    let mut comparisons = vec![compare(&hold, strategy_return, &strategy, &dates)];
+    if let Some(index) = index.and_then(|(name, candles)| Benchmark::between(name, candles, &dates))
+    {
+        comparisons.push(compare(&index, strategy_return, &strategy, &dates));
+    }
+
+    println!("{:6}-- Strategy: {:.2}%", ticker, strategy_return * 100.0);
+    for comparison in &comparisons {
+        let information_ratio = match comparison.information_ratio {
+            Some(ratio) => format!("{:.2}", ratio),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:6}-- vs {}: {:.2}% - Excess: {:.2}% - Alpha: {:.2}% - Beta: {:.2} - Correlation: {:.2} - IR: {}",
+            ticker,
+            comparison.name,
+            comparison.benchmark_return * 100.0,
+            comparison.excess_return * 100.0,
+            comparison.alpha * 100.0,
+            comparison.beta,
+            comparison.correlation,
+            information_ratio,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, daily_returns, Benchmark};
+    use crate::{apis::candles::Candle, clock, trading::Position};
+
+    fn daily_candles(closes: &[f64]) -> Vec<Candle> {
+        let mut open = closes[0];
+        closes
+            .iter()
+            .enumerate()
+            .map(|(day, close)| {
+                let time = clock::datetime(2020, 9, 1 + day as u32, 15, 59, 0);
+                let candle =
+                    Candle::new(open, *close, open.max(*close), open.min(*close), 100, time);
+                open = *close;
+                candle
+            })
+            .collect()
+    }
+
+    #[test]
+    fn buy_and_hold_returns_are_close_to_close() {
+        let hold = Benchmark::new("ABC", &daily_candles(&[10.0, 11.0, 9.9, 12.1])).unwrap();
+        assert!((hold.total_return() - 0.21).abs() < 1e-9);
+
+        let returns = hold.returns(&hold.dates());
+        let expected = [0.0, 0.1, -0.1, 0.1 / 0.45];
+        assert!(returns
+            .iter()
+            .zip(&expected)
+            .all(|(r, e)| (r - e).abs() < 1e-9));
+    }
+
+    #[test]
+    fn strategy_scaled_to_the_benchmark_has_its_beta() {
+        let index =
+            Benchmark::new("SPY", &daily_candles(&[100.0, 101.0, 99.0, 102.0, 103.0])).unwrap();
+        let dates = index.dates();
+        let strategy: Vec<f64> = index.returns(&dates).iter().map(|r| r * 2.0).collect();
+        let total = strategy.iter().sum();
+
+        let comparison = compare(&index, total, &strategy, &dates);
+        assert!((comparison.beta - 2.0).abs() < 1e-9);
+        assert!((comparison.correlation - 1.0).abs() < 1e-9);
+        assert!(comparison.alpha.abs() < 1e-9);
+        assert!(comparison.information_ratio.unwrap() > 0.0);
+
+        let ticker = "ABC".to_string();
+        let mut position = Position::open(&ticker, 10, 10.0, dates[0].and_hms(10, 0, 0));
+        position.close(12.0, dates[1].and_hms(11, 0, 0));
+        let returns = daily_returns(&[position], &dates, 1000.0);
+        assert_eq!(returns, vec![0.0, 0.02, 0.0, 0.0, 0.0]);
+    }
+}
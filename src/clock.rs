@@ -1,11 +1,15 @@
 pub use chrono::Duration;
-use chrono::{Date, DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono::{Date, DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike};
 
 pub type LocalDateTime = DateTime<Local>;
 pub type Time = NaiveTime;
 pub type DateWithoutTZ = NaiveDate;
 pub type LocalDate = Date<Local>;
 
+pub fn now() -> LocalDateTime {
+    Local::now()
+}
+
 pub fn current_date() -> DateWithoutTZ {
     Local::today().naive_local()
 }
@@ -32,6 +36,7 @@ pub fn days(days: i64) -> Duration {
     Duration::days(days)
 }
 
+#[cfg(test)]
 pub fn datetime(y: i32, month: u32, d: u32, h: u32, m: u32, s: u32) -> LocalDateTime {
     Local.ymd(y, month, d).and_hms(h, m, s)
 }
@@ -39,3 +44,30 @@ pub fn datetime(y: i32, month: u32, d: u32, h: u32, m: u32, s: u32) -> LocalDate
 pub fn day_of_week(date: LocalDate) -> i32 {
     date.format("%u").to_string().parse().unwrap()
 }
+
+pub fn is_regular_hours(datetime: LocalDateTime) -> bool {
+    let time = datetime.time();
+    time >= Time::from_hms(9, 30, 0) && time < Time::from_hms(16, 0, 0)
+}
+
+pub fn minute_of_day(datetime: LocalDateTime) -> u32 {
+    datetime.hour() * 60 + datetime.minute()
+}
+
+pub fn is_premarket(datetime: LocalDateTime) -> bool {
+    let time = datetime.time();
+    time >= Time::from_hms(4, 0, 0) && time < Time::from_hms(9, 30, 0)
+}
+
+// Weekdays from `start` up to `end`, ignoring market holidays.
+pub fn business_days_between(start: LocalDate, end: LocalDate) -> i64 {
+    let mut days = 0;
+    let mut date = start;
+    while date < end {
+        date = date + self::days(1);
+        if day_of_week(date) < 6 {
+            days += 1;
+        }
+    }
+    days
+}
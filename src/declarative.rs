@@ -0,0 +1,725 @@
+use super::{
+    apis::candles::Candle,
+    engine::Signal,
+    exits::ExitPolicy,
+    rules::{self, Condition, DayLevel, Indicator, PivotLevel, RangeLevel, ValueAreaLevel},
+    sizing::{Sizing, SizingPolicy},
+    trading::{Account, Broker, PriceData},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, fs};
+
+// A strategy described in a TOML file, e.g.
+//
+//   name = "SMA9 crossover"
+//   history = 9
+//
+//   [indicators]
+//   sma9 = { type = "sma", bars = 9 }
+//   yesterday_high = { type = "prior_day", level = "high" }
+//
+// Besides sma and atr, indicators can be levels of the session studies:
+// value_area (tick, level poc/high/low, prior), prior_day (level
+// high/low/close), pivot (level pivot/r1-r3/s1-s3), opening_range (minutes,
+// level high/low/midpoint), or relative_volume and intraday_volume (sessions)
+// and gap_percent.
+//
+//   [rules]
+//   setup = "close < sma9"
+//   entry = "close > sma9 and close > open"
+//   exit = "close < sma9 and close < open"
+//
+//   [sizing]
+//   type = "fixed_dollar"
+//   amount = 500.0
+//   max_equity_percent = 50.0
+//
+//   [stop]
+//   percent = 1.0
+//
+//   [target]
+//   percent = 2.0
+#[derive(Debug, Deserialize)]
+pub struct StrategyDefinition {
+    pub name: String,
+    #[serde(default = "default_history")]
+    pub history: usize,
+    #[serde(default)]
+    pub indicators: HashMap<String, IndicatorSpec>,
+    pub rules: RuleSpecs,
+    #[serde(default)]
+    pub sizing: SizingPolicy,
+    pub stop: Option<PercentSpec>,
+    pub target: Option<PercentSpec>,
+}
+
+fn default_history() -> usize {
+    180
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IndicatorSpec {
+    Sma {
+        bars: usize,
+    },
+    Atr {
+        bars: usize,
+    },
+    ValueArea {
+        tick: f64,
+        level: ValueAreaLevel,
+        // the last completed session instead of the developing one
+        #[serde(default)]
+        prior: bool,
+    },
+    PriorDay {
+        level: DayLevel,
+    },
+    Pivot {
+        level: PivotLevel,
+    },
+    OpeningRange {
+        minutes: i64,
+        level: RangeLevel,
+    },
+    RelativeVolume {
+        sessions: usize,
+    },
+    IntradayVolume {
+        sessions: usize,
+    },
+    GapPercent,
+}
+
+impl IndicatorSpec {
+    fn build(&self) -> Indicator {
+        match self {
+            IndicatorSpec::Sma { bars } => rules::sma(*bars),
+            IndicatorSpec::Atr { bars } => rules::atr(*bars),
+            IndicatorSpec::ValueArea { tick, level, prior } => {
+                rules::value_area(*tick, *level, *prior)
+            }
+            IndicatorSpec::PriorDay { level } => rules::prior_day(*level),
+            IndicatorSpec::Pivot { level } => rules::pivot(*level),
+            IndicatorSpec::OpeningRange { minutes, level } => {
+                rules::opening_range(*minutes, *level)
+            }
+            IndicatorSpec::RelativeVolume { sessions } => rules::relative_volume(*sessions),
+            IndicatorSpec::IntradayVolume { sessions } => rules::intraday_volume(*sessions),
+            IndicatorSpec::GapPercent => rules::gap_percent(),
+        }
+    }
+
+    // Studies can't be built over no bars or price levels zero cents apart.
+    fn check(&self, name: &str) -> Result<(), String> {
+        let (field, valid) = match self {
+            IndicatorSpec::Sma { bars } | IndicatorSpec::Atr { bars } => ("bars", *bars >= 1),
+            IndicatorSpec::ValueArea { tick, .. } => ("tick", *tick > 0.0),
+            IndicatorSpec::OpeningRange { minutes, .. } => ("minutes", *minutes >= 1),
+            IndicatorSpec::RelativeVolume { sessions }
+            | IndicatorSpec::IntradayVolume { sessions } => ("sessions", *sessions >= 1),
+            IndicatorSpec::PriorDay { .. }
+            | IndicatorSpec::Pivot { .. }
+            | IndicatorSpec::GapPercent => return Ok(()),
+        };
+        if valid {
+            Ok(())
+        } else if field == "tick" {
+            Err(format!("indicator '{}': tick must be greater than 0", name))
+        } else {
+            Err(format!(
+                "indicator '{}': {} must be at least 1",
+                name, field
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleSpecs {
+    pub setup: Option<String>,
+    pub entry: String,
+    pub exit: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PercentSpec {
+    pub percent: f64,
+}
+
+pub fn load(path: &str) -> Result<StrategyDefinition, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("couldn't open file {}: {}", path, err))?;
+    from_toml(&contents).map_err(|err| format!("invalid strategy {}: {}", path, err))
+}
+
+fn from_toml(contents: &str) -> Result<StrategyDefinition, String> {
+    let definition: StrategyDefinition = toml::from_str(contents).map_err(|err| err.to_string())?;
+
+    // build once so bad rules are reported before any data is requested
+    for (name, spec) in &definition.indicators {
+        spec.check(name)?;
+    }
+    definition.conditions()?;
+    Ok(definition)
+}
+
+struct Conditions {
+    setup: Option<Condition>,
+    entry: Condition,
+    exit: Condition,
+}
+
+impl StrategyDefinition {
+    fn conditions(&self) -> Result<Conditions, String> {
+        let setup = match &self.rules.setup {
+            Some(setup) => Some(parse(setup, &self.indicators)?),
+            None => None,
+        };
+        Ok(Conditions {
+            setup,
+            entry: parse(&self.rules.entry, &self.indicators)?,
+            exit: parse(&self.rules.exit, &self.indicators)?,
+        })
+    }
+
+    // The definition's stop and target replace the account's, so they're
+    // filled intrabar like any other exit order. Anything the definition
+    // leaves out is taken from the command line.
+    pub fn exit_policy(&self, base: ExitPolicy) -> ExitPolicy {
+        ExitPolicy {
+            stop_percent: self
+                .stop
+                .as_ref()
+                .map(|stop| stop.percent)
+                .or(base.stop_percent),
+            target_percent: self
+                .target
+                .as_ref()
+                .map(|target| target.percent)
+                .or(base.target_percent),
+            trailing: base.trailing,
+        }
+    }
+
+    // Fixed fractional sizing risks a percent of equity down to the stop, so
+    // without one every entry would be sized at 0 shares.
+    pub fn check_sizing(&self, base: ExitPolicy) -> Result<(), String> {
+        let policy = self.exit_policy(base);
+        match self.sizing.sizing {
+            Sizing::FixedFractional { .. }
+                if policy.stop_percent.is_none() && policy.trailing.is_none() =>
+            {
+                Err(format!(
+                    "{}: fixed_fractional sizing needs a [stop], --stop or --trail",
+                    self.name
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Runs a StrategyDefinition with the same setup/entry/exit flow as SmaCrossover.
+// Without a setup rule every entry signal is taken.
+pub struct DeclarativeStrategy<'a, 'd> {
+    definition: &'d StrategyDefinition,
+    conditions: Conditions,
+    setup: bool,
+    ticker: &'a String,
+}
+
+impl<'a, 'd> DeclarativeStrategy<'a, 'd> {
+    pub fn new(
+        definition: &'d StrategyDefinition,
+        ticker: &'a String,
+        candles: &[Candle],
+    ) -> Result<Self, String> {
+        let mut strategy = Self {
+            definition,
+            conditions: definition.conditions()?,
+            setup: false,
+            ticker,
+        };
+
+        // init studies
+        for candle in candles {
+            strategy.setup = strategy.update(candle).0;
+        }
+        Ok(strategy)
+    }
+
+    // Updates every rule and returns the setup, entry and exit signals.
+    fn update(&mut self, candle: &Candle) -> (bool, bool, bool) {
+        let setup = match &mut self.conditions.setup {
+            Some(setup) => setup.is_met(candle),
+            None => true,
+        };
+        let entry = self.conditions.entry.is_met(candle);
+        let exit = self.conditions.exit.is_met(candle);
+        (setup, entry, exit)
+    }
+
+    pub fn execute<B: Broker>(&mut self, price_data: &mut PriceData, account: &mut Account<'a, B>) {
+        account.exits.policy = self.definition.exit_policy(account.exits.policy);
+        while let Some(candle) = price_data.next_candle() {
+            account.add_candle(self.ticker, candle);
+            let (setup, entry, exit) = self.update(candle);
+
+            if entry && self.setup && !account.is_position_open() {
+                let shares = self.definition.sizing.shares(
+                    account,
+                    self.ticker,
+                    candle.close,
+                    candle.datetime,
+                );
+                account.send_order(
+                    self.ticker,
+                    Signal::Buy,
+                    shares,
+                    candle.close,
+                    candle.datetime,
+                );
+                self.setup = false;
+            } else if exit && account.is_position_open() {
+                account.send_order(self.ticker, Signal::Close, 0, candle.close, candle.datetime);
+            } else if setup && !account.is_position_open() {
+                self.setup = true;
+            }
+
+            account.close_position_for_day(self.ticker, candle);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    LeftParen,
+    RightParen,
+    Comma,
+    Less,
+    Greater,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' => {}
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            ',' => tokens.push(Token::Comma),
+            '<' => tokens.push(Token::Less),
+            '>' => tokens.push(Token::Greater),
+            c if c.is_ascii_digit() || c == '.' || c == '-' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let value = number
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}'", number))?;
+                tokens.push(Token::Number(value));
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+                continue;
+            }
+            c => return Err(format!("unexpected character '{}'", c)),
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+// Parses a rule such as "crosses_above(sma9, sma180) and close > open".
+//
+//   condition  := and ("or" and)*
+//   and        := unary ("and" unary)*
+//   unary      := "not" unary | "(" condition ")" | function | operand ("<" | ">") operand
+//   function   := crosses_above(a, b) | crosses_below(a, b) | above_for(a, b, bars)
+//   operand    := number | open | high | low | close | volume | indicator name
+//               | slope(operand, bars) | percent_distance(operand, operand)
+pub fn parse(
+    source: &str,
+    indicators: &HashMap<String, IndicatorSpec>,
+) -> Result<Condition, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        indicators,
+    };
+    let condition = parser.condition()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("unexpected input in rule '{}'", source));
+    }
+    Ok(condition)
+}
+
+struct Parser<'p> {
+    tokens: Vec<Token>,
+    position: usize,
+    indicators: &'p HashMap<String, IndicatorSpec>,
+}
+
+impl<'p> Parser<'p> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(format!("expected {:?}, found {:?}", expected, token)),
+        }
+    }
+
+    fn keyword(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident == word)
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let mut conditions = vec![self.and()?];
+        while self.keyword("or") {
+            self.position += 1;
+            conditions.push(self.and()?);
+        }
+        Ok(if conditions.len() == 1 {
+            conditions.pop().unwrap()
+        } else {
+            rules::or(conditions)
+        })
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut conditions = vec![self.unary()?];
+        while self.keyword("and") {
+            self.position += 1;
+            conditions.push(self.unary()?);
+        }
+        Ok(if conditions.len() == 1 {
+            conditions.pop().unwrap()
+        } else {
+            rules::and(conditions)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        if self.keyword("not") {
+            self.position += 1;
+            return Ok(rules::not(self.unary()?));
+        }
+        if self.peek() == Some(&Token::LeftParen) {
+            self.position += 1;
+            let condition = self.condition()?;
+            self.expect(Token::RightParen)?;
+            return Ok(condition);
+        }
+        for function in &["crosses_above", "crosses_below", "above_for"] {
+            if self.keyword(function) {
+                self.position += 1;
+                return self.function(function);
+            }
+        }
+
+        let a = self.operand()?;
+        match self.next() {
+            Some(Token::Less) => Ok(rules::below(a, self.operand()?)),
+            Some(Token::Greater) => Ok(rules::above(a, self.operand()?)),
+            token => Err(format!("expected comparison, found {:?}", token)),
+        }
+    }
+
+    fn function(&mut self, name: &str) -> Result<Condition, String> {
+        self.expect(Token::LeftParen)?;
+        let a = self.operand()?;
+        self.expect(Token::Comma)?;
+        let b = self.operand()?;
+        let condition = match name {
+            "crosses_above" => rules::crosses_above(a, b),
+            "crosses_below" => rules::crosses_below(a, b),
+            _ => {
+                self.expect(Token::Comma)?;
+                rules::above_for(a, b, self.bars()?)
+            }
+        };
+        self.expect(Token::RightParen)?;
+        Ok(condition)
+    }
+
+    fn bars(&mut self) -> Result<usize, String> {
+        match self.next() {
+            Some(Token::Number(bars)) if bars >= 1.0 && bars.fract() == 0.0 => Ok(bars as usize),
+            token => Err(format!("expected bar count, found {:?}", token)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Indicator, String> {
+        let ident = match self.next() {
+            Some(Token::Number(value)) => return Ok(rules::constant(value)),
+            Some(Token::Ident(ident)) => ident,
+            token => return Err(format!("expected value, found {:?}", token)),
+        };
+
+        match ident.as_str() {
+            "open" => Ok(rules::open()),
+            "high" => Ok(rules::high()),
+            "low" => Ok(rules::low()),
+            "close" => Ok(rules::close()),
+            "volume" => Ok(rules::volume()),
+            "slope" => {
+                self.expect(Token::LeftParen)?;
+                let source = self.operand()?;
+                self.expect(Token::Comma)?;
+                let bars = self.bars()?;
+                self.expect(Token::RightParen)?;
+                Ok(rules::slope(source, bars))
+            }
+            "percent_distance" => {
+                self.expect(Token::LeftParen)?;
+                let a = self.operand()?;
+                self.expect(Token::Comma)?;
+                let b = self.operand()?;
+                self.expect(Token::RightParen)?;
+                Ok(rules::percent_distance(a, b))
+            }
+            name => match self.indicators.get(name) {
+                Some(spec) => Ok(spec.build()),
+                None => Err(format!("unknown indicator '{}'", name)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_toml, parse, DeclarativeStrategy, IndicatorSpec, StrategyDefinition};
+    use crate::{
+        apis::candles::{candle, Candle},
+        clock,
+        exits::{ExitPolicy, TrailingStop},
+        simulation::SimBroker,
+        sizing::Sizing,
+        trading::{Account, PriceData},
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_a_strategy_definition() {
+        let definition: StrategyDefinition = toml::from_str(
+            r#"
+            name = "SMA9 crossover"
+            history = 9
+
+            [indicators]
+            sma9 = { type = "sma", bars = 9 }
+
+            [rules]
+            setup = "close < sma9"
+            entry = "close > sma9 and close > open"
+            exit = "close < sma9 and close < open"
+
+            [sizing]
+            type = "fixed_dollar"
+            amount = 500.0
+
+            [stop]
+            percent = 1.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(definition.history, 9);
+        assert!(matches!(
+            definition.indicators["sma9"],
+            IndicatorSpec::Sma { bars: 9 }
+        ));
+        assert!(matches!(
+            definition.sizing.sizing,
+            Sizing::FixedDollar { amount } if amount == 500.0
+        ));
+        assert_eq!(definition.stop.as_ref().unwrap().percent, 1.5);
+        assert!(definition.target.is_none());
+        assert!(definition.conditions().is_ok());
+
+        let policy = definition.exit_policy(ExitPolicy {
+            stop_percent: Some(5.0),
+            target_percent: Some(3.0),
+            trailing: Some(TrailingStop::Percent(2.0)),
+        });
+        assert_eq!(
+            policy,
+            ExitPolicy {
+                stop_percent: Some(1.5),
+                target_percent: Some(3.0),
+                trailing: Some(TrailingStop::Percent(2.0)),
+            }
+        );
+    }
+
+    const FIXED_FRACTIONAL: &str = r#"
+        name = "Up bars"
+
+        [rules]
+        entry = "close > open"
+        exit = "close < open"
+
+        [sizing]
+        type = "fixed_fractional"
+        risk_percent = 1.0
+    "#;
+
+    #[test]
+    fn fixed_fractional_sizing_uses_the_definitions_stop() {
+        let definition =
+            from_toml(&format!("{}\n[stop]\npercent = 5.0", FIXED_FRACTIONAL)).unwrap();
+        assert!(definition.check_sizing(ExitPolicy::default()).is_ok());
+
+        let ticker = "ABC".to_string();
+        let mut account = Account::new(SimBroker::new());
+        let mut price_data = PriceData::with_candles(vec![
+            candle(10.0, 10.0, 10.0, 10.0, 100, 0),
+            candle(9.9, 10.0, 10.0, 9.9, 100, 1),
+        ]);
+        DeclarativeStrategy::new(&definition, &ticker, &[])
+            .unwrap()
+            .execute(&mut price_data, &mut account);
+
+        // risking 1% of $1000 with a stop 50 cents away
+        assert_eq!(account.positions[0].shares, 20);
+    }
+
+    #[test]
+    fn fixed_fractional_sizing_without_a_stop_is_rejected() {
+        let definition = from_toml(FIXED_FRACTIONAL).unwrap();
+        assert!(definition.check_sizing(ExitPolicy::default()).is_err());
+        assert!(definition
+            .check_sizing(ExitPolicy {
+                stop_percent: Some(2.0),
+                ..ExitPolicy::default()
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn parsed_rules_evaluate_like_built_rules() {
+        let mut indicators = HashMap::new();
+        indicators.insert("sma2".to_string(), IndicatorSpec::Sma { bars: 2 });
+        let mut rule = parse(
+            "crosses_above(close, sma2) and not (close < 5)",
+            &indicators,
+        )
+        .unwrap();
+
+        let results: Vec<Option<bool>> = [
+            candle(5.0, 5.0, 5.0, 5.0, 100, 0),
+            candle(5.0, 4.0, 5.0, 4.0, 100, 1),
+            candle(4.0, 3.0, 4.0, 3.0, 100, 2),
+            candle(3.0, 6.0, 6.0, 3.0, 100, 3),
+        ]
+        .iter()
+        .map(|c| rule.update(c))
+        .collect();
+        assert_eq!(results, vec![None, None, Some(false), Some(true)]);
+    }
+
+    #[test]
+    fn session_study_levels_can_be_named_in_rules() {
+        let definition: StrategyDefinition = toml::from_str(
+            r#"
+            name = "Prior day breakout"
+
+            [indicators]
+            yesterday_high = { type = "prior_day", level = "high" }
+            poc = { type = "value_area", tick = 0.01, level = "poc", prior = true }
+            r1 = { type = "pivot", level = "r1" }
+            range_low = { type = "opening_range", minutes = 15, level = "low" }
+            rvol = { type = "relative_volume", sessions = 10 }
+            gap = { type = "gap_percent" }
+
+            [rules]
+            entry = "close > yesterday_high and close > r1 and rvol > 2"
+            exit = "close < range_low or close < poc or gap < 0"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            definition.indicators["poc"],
+            IndicatorSpec::ValueArea { prior: true, .. }
+        ));
+
+        let mut rule = parse("close > yesterday_high", &definition.indicators).unwrap();
+        let day = |d: u32, close: f64| {
+            let time = clock::datetime(2020, 9, d, 10, 0, 0);
+            Candle::new(close, close, close, close, 100, time)
+        };
+        let results: Vec<Option<bool>> = [day(28, 5.0), day(29, 4.0), day(29, 6.0)]
+            .iter()
+            .map(|c| rule.update(c))
+            .collect();
+        assert_eq!(results, vec![None, Some(false), Some(true)]);
+    }
+
+    #[test]
+    fn indicator_parameters_are_checked_when_loaded() {
+        let load = |indicator: &str| {
+            from_toml(&format!(
+                r#"
+                name = "Bad indicator"
+
+                [indicators]
+                study = {}
+
+                [rules]
+                entry = "close > study"
+                exit = "close < study"
+                "#,
+                indicator
+            ))
+            .err()
+        };
+        assert_eq!(
+            load(r#"{ type = "sma", bars = 0 }"#).unwrap(),
+            "indicator 'study': bars must be at least 1"
+        );
+        assert_eq!(
+            load(r#"{ type = "value_area", tick = 0.0, level = "poc" }"#).unwrap(),
+            "indicator 'study': tick must be greater than 0"
+        );
+        assert!(load(r#"{ type = "opening_range", minutes = 0, level = "high" }"#).is_some());
+        assert!(load(r#"{ type = "relative_volume", sessions = 0 }"#).is_some());
+        assert!(load(r#"{ type = "sma", bars = 9 }"#).is_none());
+    }
+
+    #[test]
+    fn parse_errors_name_the_problem() {
+        let indicators = HashMap::new();
+        assert_eq!(
+            parse("close > sma9", &indicators).err().unwrap(),
+            "unknown indicator 'sma9'"
+        );
+        assert!(parse("close >", &indicators).is_err());
+        assert!(parse("above_for(close, 1)", &indicators).is_err());
+        assert!(parse("above_for(close, open, 2.5)", &indicators).is_err());
+        assert!(parse("close > open open", &indicators).is_err());
+    }
+}
@@ -0,0 +1,499 @@
+use super::{
+    apis::candles::Candle,
+    clock,
+    trading::{Account, Broker, PriceData},
+};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    Buy,
+    Short,
+    Close,
+    // a stop or target resting at the broker that the candle reached, filled
+    // at its price
+    CloseAt(f64),
+}
+
+pub enum Event<'a> {
+    MarketData {
+        ticker: &'a String,
+        candle: Candle,
+    },
+    Signal {
+        ticker: &'a String,
+        signal: Signal,
+        price: f64,
+    },
+    Order {
+        ticker: &'a String,
+        signal: Signal,
+        shares: i32,
+    },
+    Fill {
+        ticker: &'a String,
+        signal: Signal,
+        shares: i32,
+    },
+    // end of bar housekeeping for the ticker, e.g. end of day closes
+    Timer {
+        ticker: &'a String,
+    },
+}
+
+impl Event<'_> {
+    // Events at the same time are handled in this order, then first in first
+    // out, so every candle at a time is seen before any signal from them.
+    fn rank(&self) -> u8 {
+        match self {
+            Event::MarketData { .. } => 0,
+            Event::Signal { .. } => 1,
+            Event::Order { .. } => 2,
+            Event::Fill { .. } => 3,
+            Event::Timer { .. } => 4,
+        }
+    }
+}
+
+struct Scheduled<'a> {
+    time: clock::LocalDateTime,
+    rank: u8,
+    sequence: u64,
+    event: Event<'a>,
+}
+
+impl Scheduled<'_> {
+    fn key(&self) -> (clock::LocalDateTime, u8, u64) {
+        (self.time, self.rank, self.sequence)
+    }
+}
+
+impl PartialEq for Scheduled<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Scheduled<'_> {}
+
+impl PartialOrd for Scheduled<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed so the heap pops the earliest event first.
+impl Ord for Scheduled<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+#[derive(Default)]
+pub struct EventQueue<'a> {
+    heap: BinaryHeap<Scheduled<'a>>,
+    sequence: u64,
+}
+
+impl<'a> EventQueue<'a> {
+    pub fn push(&mut self, time: clock::LocalDateTime, event: Event<'a>) {
+        self.sequence += 1;
+        self.heap.push(Scheduled {
+            time,
+            rank: event.rank(),
+            sequence: self.sequence,
+            event,
+        });
+    }
+
+    pub fn pop(&mut self) -> Option<(clock::LocalDateTime, Event<'a>)> {
+        self.heap
+            .pop()
+            .map(|scheduled| (scheduled.time, scheduled.event))
+    }
+
+    pub fn next_time(&self) -> Option<clock::LocalDateTime> {
+        self.heap.peek().map(|scheduled| scheduled.time)
+    }
+}
+
+// A strategy driven by the engine. It only decides; the engine sizes, sends
+// and fills the orders.
+pub trait EventStrategy {
+    // Called with each candle of the strategy's ticker after the account has
+    // seen it. open is whether the ticker has an open position.
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal>;
+
+    // Called when one of the strategy's orders fills. Orders can be rejected,
+    // e.g. by the risk limits, so entries aren't known to be open before this.
+    fn on_fill(&mut self, _signal: Signal) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillAt {
+    // the last close when the fill arrives, the signal's own close without
+    // latency
+    Close,
+    // the open of the first bar after the signal's, once the latency has
+    // passed, so no order fills on the bar that produced it
+    NextOpen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EngineConfig {
+    // delay between sending an order and its fill
+    pub latency: clock::Duration,
+    pub fill_at: FillAt,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            latency: clock::Duration::zero(),
+            fill_at: FillAt::Close,
+        }
+    }
+}
+
+// An order waiting for the next bar's open.
+struct PendingOrder<'a> {
+    ticker: &'a String,
+    signal: Signal,
+    shares: i32,
+    sent: clock::LocalDateTime,
+}
+
+// Handles market data, signals, orders, fills and timers from one queue in
+// time order. Backtests queue all their candles up front; paper trading queues
+// candles as they arrive and runs up to the clock.
+pub struct Engine<'a, 's> {
+    pub config: EngineConfig,
+    queue: EventQueue<'a>,
+    strategies: HashMap<&'a String, &'s mut dyn EventStrategy>,
+    last_candles: HashMap<&'a String, Candle>,
+    pending: Vec<PendingOrder<'a>>,
+}
+
+impl<'a, 's> Engine<'a, 's> {
+    pub fn new(config: EngineConfig) -> Self {
+        Self {
+            config,
+            queue: EventQueue::default(),
+            strategies: HashMap::new(),
+            last_candles: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn add_strategy(&mut self, ticker: &'a String, strategy: &'s mut dyn EventStrategy) {
+        self.strategies.insert(ticker, strategy);
+    }
+
+    pub fn market_data(&mut self, ticker: &'a String, candle: Candle) {
+        self.queue
+            .push(candle.datetime, Event::MarketData { ticker, candle });
+    }
+
+    pub fn run<B: Broker>(&mut self, account: &mut Account<'a, B>) {
+        while let Some((time, event)) = self.queue.pop() {
+            self.handle(time, event, account);
+        }
+    }
+
+    // Handles every event up to and including time. Fills delayed by the
+    // latency wait in the queue until the clock reaches them.
+    pub fn run_until<B: Broker>(
+        &mut self,
+        time: clock::LocalDateTime,
+        account: &mut Account<'a, B>,
+    ) {
+        while self.queue.next_time().is_some_and(|next| next <= time) {
+            let (time, event) = self.queue.pop().unwrap();
+            self.handle(time, event, account);
+        }
+    }
+
+    fn handle<B: Broker>(
+        &mut self,
+        time: clock::LocalDateTime,
+        event: Event<'a>,
+        account: &mut Account<'a, B>,
+    ) {
+        match event {
+            Event::MarketData { ticker, candle } => {
+                account.advance(time);
+                self.fill_pending(ticker, &candle, account);
+                account.add_candle(ticker, &candle);
+                let open = account.position_for(ticker).is_some();
+                if let Some(strategy) = self.strategies.get_mut(ticker) {
+                    if let Some(signal) = strategy.on_candle(&candle, open) {
+                        let price = candle.close;
+                        self.queue.push(
+                            time,
+                            Event::Signal {
+                                ticker,
+                                signal,
+                                price,
+                            },
+                        );
+                    }
+                }
+                self.last_candles.insert(ticker, candle);
+                self.queue.push(time, Event::Timer { ticker });
+            }
+            Event::Signal {
+                ticker,
+                signal,
+                price,
+            } => {
+                let shares = match signal {
+                    Signal::Buy | Signal::Short => account.position_size(ticker, price, time),
+                    Signal::Close | Signal::CloseAt(_) => match account.position_for(ticker) {
+                        Some(position) => position.shares.abs(),
+                        None => return,
+                    },
+                };
+                self.queue.push(
+                    time,
+                    Event::Order {
+                        ticker,
+                        signal,
+                        shares,
+                    },
+                );
+            }
+            Event::Order {
+                ticker,
+                signal,
+                shares,
+            } => match (signal, self.config.fill_at) {
+                // already at the broker, so neither the latency nor the next
+                // open applies
+                (Signal::CloseAt(price), _) => {
+                    self.fill(account, ticker, signal, shares, price, time)
+                }
+                (_, FillAt::Close) => self.queue.push(
+                    time + self.config.latency,
+                    Event::Fill {
+                        ticker,
+                        signal,
+                        shares,
+                    },
+                ),
+                (_, FillAt::NextOpen) => self.pending.push(PendingOrder {
+                    ticker,
+                    signal,
+                    shares,
+                    sent: time,
+                }),
+            },
+            Event::Fill {
+                ticker,
+                signal,
+                shares,
+            } => {
+                if let Some(close) = self.last_candles.get(ticker).map(|candle| candle.close) {
+                    self.fill(account, ticker, signal, shares, close, time);
+                }
+            }
+            Event::Timer { ticker } => {
+                if let Some(candle) = self.last_candles.get(ticker) {
+                    account.close_position_for_day(ticker, candle);
+                }
+            }
+        }
+    }
+
+    // Fills the ticker's orders that are due at the candle's open, before the
+    // account or the strategy see the rest of the candle.
+    fn fill_pending<B: Broker>(
+        &mut self,
+        ticker: &'a String,
+        candle: &Candle,
+        account: &mut Account<'a, B>,
+    ) {
+        let latency = self.config.latency;
+        let (due, waiting) = self.pending.drain(..).partition(|order: &PendingOrder| {
+            order.ticker == ticker
+                && candle.datetime > order.sent
+                && candle.datetime >= order.sent + latency
+        });
+        self.pending = waiting;
+        for order in due {
+            self.fill(
+                account,
+                order.ticker,
+                order.signal,
+                order.shares,
+                candle.open,
+                candle.datetime,
+            );
+        }
+    }
+
+    // Tells the strategy when the order changed its position.
+    fn fill<B: Broker>(
+        &mut self,
+        account: &mut Account<'a, B>,
+        ticker: &'a String,
+        signal: Signal,
+        shares: i32,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) {
+        let open = account.position_for(ticker).is_some();
+        account.fill_order(ticker, signal, shares, price, time);
+        if account.position_for(ticker).is_some() != open {
+            if let Some(strategy) = self.strategies.get_mut(ticker) {
+                strategy.on_fill(signal);
+            }
+        }
+    }
+}
+
+// Runs a strategy over the candles price_data has left, the way backtests do.
+pub fn run_strategy<'a, B: Broker>(
+    ticker: &'a String,
+    strategy: &mut dyn EventStrategy,
+    price_data: &mut PriceData,
+    account: &mut Account<'a, B>,
+    config: EngineConfig,
+) {
+    let mut engine = Engine::new(config);
+    while let Some(candle) = price_data.next_candle() {
+        engine.market_data(ticker, candle.clone());
+    }
+    engine.add_strategy(ticker, strategy);
+    engine.run(account);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Engine, EngineConfig, Event, EventQueue, EventStrategy, FillAt, Signal};
+    use crate::{
+        apis::candles::{candle, Candle},
+        backtest::BacktestBroker,
+        clock,
+        trading::Account,
+    };
+
+    // Buys on the first candle and closes on the third.
+    struct Scripted {
+        candles: usize,
+    }
+
+    impl EventStrategy for Scripted {
+        fn on_candle(&mut self, _candle: &Candle, open: bool) -> Option<Signal> {
+            self.candles += 1;
+            match self.candles {
+                1 => Some(Signal::Buy),
+                3 if open => Some(Signal::Close),
+                _ => None,
+            }
+        }
+    }
+
+    fn fills(config: EngineConfig) -> (f64, Option<f64>) {
+        let ticker = "ABC".to_string();
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        let mut strategy = Scripted { candles: 0 };
+        let mut engine = Engine::new(config);
+        engine.add_strategy(&ticker, &mut strategy);
+        for (minute, close) in [10.0, 11.0, 12.0, 13.0].iter().enumerate() {
+            engine.market_data(
+                &ticker,
+                candle(
+                    *close - 0.5,
+                    *close,
+                    *close,
+                    *close - 0.5,
+                    100,
+                    minute as i64,
+                ),
+            );
+        }
+        engine.run(&mut account);
+        let position = &account.positions[0];
+        (position.bid, position.closes.first().map(|close| close.ask))
+    }
+
+    #[test]
+    fn queue_pops_by_time_then_event_kind() {
+        let ticker = "ABC".to_string();
+        let mut queue = EventQueue::default();
+        let first = candle(9.5, 10.0, 10.0, 9.5, 100, 0);
+        let second = candle(10.5, 11.0, 11.0, 10.5, 100, 1);
+        queue.push(second.datetime, Event::Timer { ticker: &ticker });
+        queue.push(first.datetime, Event::Timer { ticker: &ticker });
+        queue.push(
+            first.datetime,
+            Event::MarketData {
+                ticker: &ticker,
+                candle: first.clone(),
+            },
+        );
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop())
+            .map(|(time, event)| (time, event.rank()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (first.datetime, 0),
+                (first.datetime, 4),
+                (second.datetime, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn orders_fill_after_the_latency_at_the_last_price() {
+        assert_eq!(fills(EngineConfig::default()), (10.0, Some(12.0)));
+        let latency = EngineConfig {
+            latency: clock::Duration::minutes(1),
+            ..EngineConfig::default()
+        };
+        assert_eq!(fills(latency), (11.0, Some(13.0)));
+    }
+
+    #[test]
+    fn running_until_a_time_leaves_later_fills_queued() {
+        let ticker = "ABC".to_string();
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        let mut strategy = Scripted { candles: 0 };
+        let mut engine = Engine::new(EngineConfig {
+            latency: clock::Duration::minutes(1),
+            ..EngineConfig::default()
+        });
+        engine.add_strategy(&ticker, &mut strategy);
+
+        let first = candle(9.5, 10.0, 10.0, 9.5, 100, 0);
+        let time = first.datetime;
+        engine.market_data(&ticker, first);
+        engine.run_until(time, &mut account);
+        assert!(account.positions.is_empty());
+
+        engine.market_data(&ticker, candle(10.5, 11.0, 11.0, 10.5, 100, 1));
+        engine.run_until(time + clock::Duration::minutes(1), &mut account);
+        assert_eq!(account.positions[0].bid, 11.0);
+    }
+
+    #[test]
+    fn next_open_fills_wait_for_the_following_bar() {
+        let next_open = EngineConfig {
+            fill_at: FillAt::NextOpen,
+            ..EngineConfig::default()
+        };
+        assert_eq!(fills(next_open), (10.5, Some(12.5)));
+
+        // the close on the third bar has no fourth bar to fill on
+        let slow = EngineConfig {
+            latency: clock::Duration::minutes(2),
+            ..next_open
+        };
+        assert_eq!(fills(slow), (11.5, None));
+    }
+}
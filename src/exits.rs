@@ -0,0 +1,371 @@
+use super::{apis::candles::Candle, studies};
+use std::collections::HashMap;
+
+// Exits the engine places for every position an Account opens. They're checked
+// against each candle's open, high and low before the strategy sees its close.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExitPolicy {
+    // distances from the entry price, in percent
+    pub stop_percent: Option<f64>,
+    pub target_percent: Option<f64>,
+    pub trailing: Option<TrailingStop>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrailingStop {
+    // dollars from the best price since entry
+    Fixed(f64),
+    // percent from the best price since entry
+    Percent(f64),
+    // ATR multiple from the best close since entry
+    Atr { bars: usize, multiple: f64 },
+    // ATR multiple from the highest high (lowest low for shorts) since entry
+    Chandelier { bars: usize, multiple: f64 },
+}
+
+impl TrailingStop {
+    // e.g. "0.50", "2%", "atr:14:3" or "chandelier:22:3"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let error = || format!("invalid trailing stop: {}", spec);
+        let parts: Vec<String> = spec.split(':').map(|p| p.to_lowercase()).collect();
+        match parts.as_slice() {
+            [kind, bars, multiple] if kind == "atr" || kind == "chandelier" => {
+                let bars = bars.parse().map_err(|_| error())?;
+                let multiple = multiple.parse().map_err(|_| error())?;
+                if kind == "atr" {
+                    Ok(TrailingStop::Atr { bars, multiple })
+                } else {
+                    Ok(TrailingStop::Chandelier { bars, multiple })
+                }
+            }
+            [amount] => match amount.strip_suffix('%') {
+                Some(percent) => percent
+                    .parse()
+                    .map(TrailingStop::Percent)
+                    .map_err(|_| error()),
+                None => amount.parse().map(TrailingStop::Fixed).map_err(|_| error()),
+            },
+            _ => Err(error()),
+        }
+    }
+
+    fn atr_bars(&self) -> Option<usize> {
+        match self {
+            TrailingStop::Atr { bars, .. } | TrailingStop::Chandelier { bars, .. } => Some(*bars),
+            _ => None,
+        }
+    }
+}
+
+// The orders working for one open position. The stop and target form a
+// bracket: when either fills the position is closed and the other is cancelled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExitOrders {
+    pub stop: Option<f64>,
+    pub target: Option<f64>,
+    pub trailing_stop: Option<f64>,
+    short: bool,
+    best_price: f64,
+    best_close: f64,
+}
+
+impl ExitOrders {
+    pub fn new(policy: &ExitPolicy, entry: f64, short: bool) -> Self {
+        let direction = if short { -1.0 } else { 1.0 };
+        Self {
+            stop: policy
+                .stop_percent
+                .map(|percent| entry * (1.0 - direction * percent / 100.0)),
+            target: policy
+                .target_percent
+                .map(|percent| entry * (1.0 + direction * percent / 100.0)),
+            trailing_stop: None,
+            short,
+            best_price: entry,
+            best_close: entry,
+        }
+    }
+
+    // The tighter of the fixed and trailing stops.
+    pub fn effective_stop(&self) -> Option<f64> {
+        match (self.stop, self.trailing_stop) {
+            (Some(stop), Some(trailing)) if self.short => Some(stop.min(trailing)),
+            (Some(stop), Some(trailing)) => Some(stop.max(trailing)),
+            (stop, trailing) => stop.or(trailing),
+        }
+    }
+
+    pub fn fill_price(&self, candle: &Candle) -> Option<f64> {
+        fill_price(candle, self.effective_stop(), self.target, self.short)
+    }
+
+    // Trailing stops only move in the position's favor. They're moved after the
+    // candle has been checked, so one bar can't both raise a stop and hit it.
+    fn trail(&mut self, trailing: TrailingStop, candle: &Candle, atr: Option<f64>) {
+        let level = if self.short {
+            self.best_price = self.best_price.min(candle.low);
+            self.best_close = self.best_close.min(candle.close);
+            match trailing {
+                TrailingStop::Fixed(amount) => Some(self.best_price + amount),
+                TrailingStop::Percent(percent) => Some(self.best_price * (1.0 + percent / 100.0)),
+                TrailingStop::Atr { multiple, .. } => {
+                    atr.map(|atr| self.best_close + atr * multiple)
+                }
+                TrailingStop::Chandelier { multiple, .. } => {
+                    atr.map(|atr| self.best_price + atr * multiple)
+                }
+            }
+        } else {
+            self.best_price = self.best_price.max(candle.high);
+            self.best_close = self.best_close.max(candle.close);
+            match trailing {
+                TrailingStop::Fixed(amount) => Some(self.best_price - amount),
+                TrailingStop::Percent(percent) => Some(self.best_price * (1.0 - percent / 100.0)),
+                TrailingStop::Atr { multiple, .. } => {
+                    atr.map(|atr| self.best_close - atr * multiple)
+                }
+                TrailingStop::Chandelier { multiple, .. } => {
+                    atr.map(|atr| self.best_price - atr * multiple)
+                }
+            }
+        };
+
+        if let Some(level) = level {
+            self.trailing_stop = Some(match self.trailing_stop {
+                Some(current) if self.short => current.min(level),
+                Some(current) => current.max(level),
+                None => level,
+            });
+        }
+    }
+}
+
+// Exit orders for each ticker with an open position, and the ATRs that ATR
+// based trailing stops need. ATRs are updated on every candle, held or not.
+#[derive(Default)]
+pub struct Exits {
+    pub policy: ExitPolicy,
+    orders: HashMap<String, ExitOrders>,
+    atrs: HashMap<String, studies::ATR>,
+}
+
+impl Exits {
+    pub fn new(policy: ExitPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    pub fn open(&mut self, ticker: &str, entry: f64, short: bool) {
+        let orders = ExitOrders::new(&self.policy, entry, short);
+        self.orders.insert(ticker.to_string(), orders);
+    }
+
+    pub fn cancel(&mut self, ticker: &str) {
+        self.orders.remove(ticker);
+    }
+
+    #[cfg(test)]
+    pub fn orders_for(&self, ticker: &str) -> Option<&ExitOrders> {
+        self.orders.get(ticker)
+    }
+
+    // Distance from an entry at price to the tightest stop the policy would
+    // place, None when it places no stops.
+    pub fn stop_distance(&self, ticker: &str, price: f64) -> Option<f64> {
+        let fixed = self
+            .policy
+            .stop_percent
+            .map(|percent| price * percent / 100.0);
+        let trailing = self.policy.trailing.and_then(|trailing| match trailing {
+            TrailingStop::Fixed(amount) => Some(amount),
+            TrailingStop::Percent(percent) => Some(price * percent / 100.0),
+            TrailingStop::Atr { multiple, .. } | TrailingStop::Chandelier { multiple, .. } => self
+                .atrs
+                .get(ticker)
+                .and_then(|atr| atr.value)
+                .map(|atr| atr * multiple),
+        });
+        match (fixed, trailing) {
+            (Some(fixed), Some(trailing)) => Some(fixed.min(trailing)),
+            (fixed, trailing) => fixed.or(trailing),
+        }
+    }
+
+    // Returns the fill price when one of the ticker's orders is hit by the candle,
+    // otherwise moves its trailing stop.
+    pub fn update(&mut self, ticker: &str, candle: &Candle) -> Option<f64> {
+        let fill = self
+            .orders
+            .get(ticker)
+            .and_then(|orders| orders.fill_price(candle));
+
+        let atr = self
+            .policy
+            .trailing
+            .and_then(|t| t.atr_bars())
+            .and_then(|bars| {
+                let atr = self
+                    .atrs
+                    .entry(ticker.to_string())
+                    .or_insert_with(|| studies::ATR::new(bars));
+                atr.add(candle);
+                atr.value
+            });
+
+        if fill.is_none() {
+            if let (Some(orders), Some(trailing)) =
+                (self.orders.get_mut(ticker), self.policy.trailing)
+            {
+                orders.trail(trailing, candle, atr);
+            }
+        }
+        fill
+    }
+}
+
+// Fill price when a candle reaches a stop or target. The open trades first, so
+// a gap through either fills at the open. The order of the high and low within
+// a candle is unknown, so the stop is assumed to be hit before the target.
+pub fn fill_price(
+    candle: &Candle,
+    stop: Option<f64>,
+    target: Option<f64>,
+    short: bool,
+) -> Option<f64> {
+    let stopped =
+        |price: f64| stop.is_some_and(|stop| if short { price >= stop } else { price <= stop });
+    let targeted = |price: f64| {
+        target.is_some_and(|target| {
+            if short {
+                price <= target
+            } else {
+                price >= target
+            }
+        })
+    };
+    let (adverse, favorable) = if short {
+        (candle.high, candle.low)
+    } else {
+        (candle.low, candle.high)
+    };
+
+    if stopped(candle.open) || targeted(candle.open) {
+        Some(candle.open)
+    } else if stopped(adverse) {
+        stop
+    } else if targeted(favorable) {
+        target
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_price, ExitPolicy, Exits, TrailingStop};
+    use crate::apis::candles::candle;
+
+    #[test]
+    fn stop_is_assumed_hit_before_target_within_a_candle() {
+        let bar = candle(10.0, 10.0, 11.0, 9.0, 100, 0);
+        assert_eq!(fill_price(&bar, Some(9.5), Some(10.5), false), Some(9.5));
+        assert_eq!(fill_price(&bar, Some(10.5), Some(9.5), true), Some(10.5));
+        assert_eq!(fill_price(&bar, None, Some(10.5), false), Some(10.5));
+        assert_eq!(fill_price(&bar, Some(8.0), Some(12.0), false), None);
+    }
+
+    #[test]
+    fn gaps_through_a_stop_or_target_fill_at_the_open() {
+        let gap_down = candle(9.0, 9.5, 9.8, 8.5, 100, 0);
+        assert_eq!(
+            fill_price(&gap_down, Some(9.5), Some(11.0), false),
+            Some(9.0)
+        );
+        let gap_up = candle(11.2, 11.0, 11.5, 9.0, 100, 0);
+        assert_eq!(
+            fill_price(&gap_up, Some(9.5), Some(11.0), false),
+            Some(11.2)
+        );
+    }
+
+    #[test]
+    fn bracket_is_placed_around_the_entry() {
+        let policy = ExitPolicy {
+            stop_percent: Some(2.0),
+            target_percent: Some(4.0),
+            trailing: None,
+        };
+        let mut exits = Exits::new(policy);
+        exits.open("ABC", 100.0, true);
+        let orders = exits.orders_for("ABC").unwrap();
+        assert_eq!(orders.stop, Some(102.0));
+        assert_eq!(orders.target, Some(96.0));
+
+        assert_eq!(
+            exits.update("ABC", &candle(99.0, 96.0, 99.5, 95.0, 100, 0)),
+            Some(96.0)
+        );
+    }
+
+    #[test]
+    fn trailing_stop_ratchets_after_each_candle() {
+        let policy = ExitPolicy {
+            trailing: Some(TrailingStop::Percent(10.0)),
+            ..ExitPolicy::default()
+        };
+        let mut exits = Exits::new(policy);
+        exits.open("ABC", 10.0, false);
+
+        // the new high raises the stop only after this candle is checked
+        assert_eq!(
+            exits.update("ABC", &candle(10.0, 11.0, 12.0, 10.0, 100, 0)),
+            None
+        );
+        assert_eq!(exits.orders_for("ABC").unwrap().trailing_stop, Some(10.8));
+
+        assert_eq!(
+            exits.update("ABC", &candle(11.0, 11.0, 11.5, 10.9, 100, 1)),
+            None
+        );
+        assert_eq!(exits.orders_for("ABC").unwrap().trailing_stop, Some(10.8));
+
+        assert_eq!(
+            exits.update("ABC", &candle(11.0, 10.6, 11.0, 10.5, 100, 2)),
+            Some(10.8)
+        );
+    }
+
+    #[test]
+    fn chandelier_stop_waits_for_the_atr() {
+        let policy = ExitPolicy {
+            trailing: Some(TrailingStop::Chandelier {
+                bars: 2,
+                multiple: 2.0,
+            }),
+            ..ExitPolicy::default()
+        };
+        let mut exits = Exits::new(policy);
+        exits.update("ABC", &candle(10.0, 10.0, 10.5, 9.5, 100, 0));
+        exits.open("ABC", 10.0, false);
+
+        exits.update("ABC", &candle(10.0, 10.5, 11.0, 10.0, 100, 1));
+        // ATR(2) = (1.0 + 1.0) / 2, highest high 11.0
+        assert_eq!(exits.orders_for("ABC").unwrap().trailing_stop, Some(9.0));
+    }
+
+    #[test]
+    fn trailing_stops_are_parsed_from_the_command_line() {
+        assert_eq!(TrailingStop::parse("0.5"), Ok(TrailingStop::Fixed(0.5)));
+        assert_eq!(TrailingStop::parse("2%"), Ok(TrailingStop::Percent(2.0)));
+        assert_eq!(
+            TrailingStop::parse("ATR:14:3"),
+            Ok(TrailingStop::Atr {
+                bars: 14,
+                multiple: 3.0
+            })
+        );
+        assert!(TrailingStop::parse("chandelier:x:3").is_err());
+    }
+}
@@ -1,16 +1,37 @@
 mod apis;
 mod backtest;
+mod benchmark;
 mod clock;
 mod config;
+mod declarative;
+mod engine;
+mod exits;
+mod metrics;
+mod montecarlo;
+mod optimizer;
+mod output;
+mod paper;
+mod pdt;
+mod random;
+mod report;
+mod risk;
+mod rules;
+mod scripting;
+// the baseline simulation tests compare booleans with assert_eq!
+#[cfg_attr(test, allow(clippy::bool_assert_comparison))]
 mod simulation;
+mod sizing;
 mod strategies;
 mod studies;
+mod timeframes;
 mod trading;
+mod walkforward;
 
 use std::env;
 
 fn main() {
-    let args: Vec<String> = env::args().map(|a| a.to_uppercase()).collect();
+    let raw_args: Vec<String> = env::args().collect();
+    let args: Vec<String> = raw_args.iter().map(|a| a.to_uppercase()).collect();
     if args.len() < 2 {
         eprintln!("Must provide at least one symbol to use");
         return;
@@ -20,16 +41,256 @@ fn main() {
     match args[1].as_str() {
         "--BACKTEST" => {
             println!("Backtesting");
-            if args[2] == "-V" {
-                backtest::run_backtest(&args[3..], &env, true);
-            } else {
-                backtest::run_backtest(&args[2..], &env, false);
+            let mut start = 2;
+            let verbose = arg(&args, start) == "-V";
+            if verbose {
+                start += 1;
+            }
+            let reports = match parse_reports(&args, &mut start) {
+                Ok(reports) => reports,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
+                }
+            };
+            let settings = match parse_settings(&args, &raw_args, &mut start) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
+                }
+            };
+
+            let mut source = backtest::StrategySource::Builtin(strategies::Builtin::SmaCrossover);
+            if arg(&args, start) == "--BUILTIN" {
+                let builtin = strategies::Builtin::parse(arg(&args, start + 1))
+                    .and_then(|builtin| settings.check_builtin(builtin).map(|_| builtin));
+                match builtin {
+                    Ok(builtin) => source = backtest::StrategySource::Builtin(builtin),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                }
+                start += 2;
+            } else if arg(&args, start) == "--STRATEGY" {
+                let definition = file_path(&raw_args, start + 1, "--strategy")
+                    .and_then(declarative::load)
+                    .and_then(|definition| {
+                        definition.check_sizing(settings.exits).map(|_| definition)
+                    });
+                match definition {
+                    Ok(definition) => {
+                        source = backtest::StrategySource::Definition(Box::new(definition))
+                    }
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                }
+                start += 2;
+            } else if arg(&args, start) == "--PAIRS" {
+                if let Err(err) = reports.check_pairs() {
+                    eprintln!("{}", err);
+                    return;
+                }
+                backtest::run_pairs_backtest(&args[start + 1..], &env, verbose, &settings);
+                return;
+            } else if arg(&args, start) == "--SCRIPT" {
+                match file_path(&raw_args, start + 1, "--script") {
+                    Ok(path) => source = backtest::StrategySource::Script(path.to_string()),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                }
+                start += 2;
+            }
+            match tickers(&args, start) {
+                Ok(tickers) => {
+                    backtest::run_backtest(tickers, &env, verbose, &source, &settings, &reports)
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        "--OPTIMIZE" => {
+            let mut start = 2;
+            let search = parse_settings(&args, &raw_args, &mut start).and_then(|settings| {
+                let (configs, search) = parse_sweep(&args, &mut start, None)?;
+                optimizer::check_history(&configs, &settings.holding)?;
+                Ok((settings, configs, search, tickers(&args, start)?))
+            });
+            match search {
+                Ok((settings, configs, search, tickers)) => {
+                    optimizer::run_optimizer(tickers, &env, &configs, search, &settings)
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        "--WALK-FORWARD" => {
+            let mut start = 2;
+            let mut windows = walkforward::WindowConfig::default();
+            let search = parse_settings(&args, &raw_args, &mut start).and_then(|settings| {
+                let (configs, search) = parse_sweep(&args, &mut start, Some(&mut windows))?;
+                optimizer::check_history(&configs, &settings.holding)?;
+                Ok((settings, configs, search, tickers(&args, start)?))
+            });
+            match search {
+                Ok((settings, configs, search, tickers)) => walkforward::run_walk_forward(
+                    tickers, &env, &configs, search, &settings, windows,
+                ),
+                Err(err) => eprintln!("{}", err),
             }
         }
         "--SIM" => {
-            simulation::run_simulation(&args[2..], &env);
+            let margin = args.get(2).map(String::as_str) == Some("--MARGIN");
+            let start = if margin { 3 } else { 2 };
+            simulation::run_simulation(&args[start..], &env, margin);
         }
-        "--PAPER" => println!("Paper trading not implemented yet"),
+        "--PAPER" => match tickers(&args, 2) {
+            Ok(tickers) => paper::run_paper(&tickers[0], &env),
+            Err(err) => eprintln!("{}", err),
+        },
         _ => println!("Live trading not implemented yet"),
     }
 }
+
+// The argument at index, or "" past the end so option checks fall through.
+fn arg(args: &[String], index: usize) -> &str {
+    args.get(index).map(String::as_str).unwrap_or("")
+}
+
+// The tickers left after the options.
+fn tickers(args: &[String], start: usize) -> Result<&[String], String> {
+    match args.get(start..) {
+        Some(tickers) if !tickers.is_empty() => Ok(tickers),
+        _ => Err("Must provide at least one symbol to use".to_string()),
+    }
+}
+
+// The file path after an option. Paths are case sensitive, so they come from
+// the raw args.
+fn file_path<'r>(raw_args: &'r [String], index: usize, option: &str) -> Result<&'r str, String> {
+    raw_args
+        .get(index)
+        .map(String::as_str)
+        .ok_or_else(|| format!("{} needs a file", option))
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    value
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percent: {}", value))
+}
+
+// Reports printed or written after each backtest. Advances start past them.
+fn parse_reports(args: &[String], start: &mut usize) -> Result<backtest::Reports, String> {
+    let mut reports = backtest::Reports::default();
+    loop {
+        let value = arg(args, *start + 1);
+        match arg(args, *start) {
+            "--MONTE-CARLO" => {
+                let runs = value
+                    .parse()
+                    .map_err(|_| format!("invalid number of runs: {}", value))?;
+                reports.monte_carlo = Some(montecarlo::MonteCarloConfig {
+                    runs,
+                    ..montecarlo::MonteCarloConfig::default()
+                });
+            }
+            "--BENCHMARK" => reports.benchmark = Some(value.to_string()),
+            "--OUTPUT" => reports.output = Some(output::Format::parse(value)?),
+            "--HTML" => {
+                reports.html = true;
+                *start += 1;
+                continue;
+            }
+            _ => break,
+        }
+        *start += 2;
+    }
+    Ok(reports)
+}
+
+// Parameter sets for the optimizer, how they were picked, and the window
+// lengths when walking forward.
+fn parse_sweep(
+    args: &[String],
+    start: &mut usize,
+    mut windows: Option<&mut walkforward::WindowConfig>,
+) -> Result<(Vec<strategies::SmaCrossConfig>, optimizer::Search), String> {
+    let mut fast = optimizer::Range::parse("5:15:2")?;
+    let mut slow = optimizer::Range::parse("60:240:30")?;
+    let mut samples = None;
+    let mut seed = 1;
+    loop {
+        let value = arg(args, *start + 1);
+        let days = || match value.parse::<usize>() {
+            Ok(days) if days > 0 => Ok(days),
+            _ => Err(format!("invalid number of days: {}", value)),
+        };
+        match (arg(args, *start), windows.as_deref_mut()) {
+            ("--FAST", _) => fast = optimizer::Range::parse(value)?,
+            ("--SLOW", _) => slow = optimizer::Range::parse(value)?,
+            ("--RANDOM", _) => {
+                let count = value
+                    .parse()
+                    .map_err(|_| format!("invalid sample count: {}", value))?;
+                samples = Some(count);
+            }
+            ("--SEED", _) => {
+                seed = value
+                    .parse()
+                    .map_err(|_| format!("invalid seed: {}", value))?;
+            }
+            ("--IN-SAMPLE", Some(windows)) => windows.in_sample_days = days()?,
+            ("--OUT-OF-SAMPLE", Some(windows)) => windows.out_of_sample_days = days()?,
+            _ => break,
+        }
+        *start += 2;
+    }
+    let search = match samples {
+        Some(samples) => optimizer::Search::Random { samples, seed },
+        None => optimizer::Search::Grid,
+    };
+    Ok((optimizer::parameter_sets(&fast, &slow, search), search))
+}
+
+// Account options shared by backtests and the optimizer. Advances start past them.
+fn parse_settings(
+    args: &[String],
+    raw_args: &[String],
+    start: &mut usize,
+) -> Result<backtest::AccountSettings, String> {
+    let mut settings = backtest::AccountSettings::default();
+    if arg(args, *start) == "--SWING" {
+        settings.holding = trading::Holding::Swing(trading::SwingConfig::default());
+        *start += 1;
+    }
+    loop {
+        let value = arg(args, *start + 1);
+        match arg(args, *start) {
+            "--STOP" => settings.exits.stop_percent = Some(parse_percent(value)?),
+            "--TARGET" => settings.exits.target_percent = Some(parse_percent(value)?),
+            "--TRAIL" => settings.exits.trailing = Some(exits::TrailingStop::parse(value)?),
+            "--SIZE" => settings.sizing.sizing = sizing::Sizing::parse(value)?,
+            "--MAX-POSITION" => settings.sizing.max_equity_percent = Some(parse_percent(value)?),
+            // file paths are case sensitive, so they come from the raw args
+            "--RISK" => settings.risk = risk::load(file_path(raw_args, *start + 1, "--risk")?)?,
+            "--NEXT-OPEN" => {
+                settings.engine.fill_at = engine::FillAt::NextOpen;
+                *start += 1;
+                continue;
+            }
+            "--PDT" => {
+                settings.pattern_day_trader_rule = true;
+                *start += 1;
+                continue;
+            }
+            _ => break,
+        }
+        *start += 2;
+    }
+    Ok(settings)
+}
@@ -0,0 +1,176 @@
+use super::{clock, trading::Position};
+
+// Results of closed positions, in the order they were closed. Break even
+// trades count as wins, like the backtest log.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub trades: usize,
+    pub wins: usize,
+    pub gross_profit: f64,
+    pub gross_loss: f64,
+    // largest drop from a peak in cumulative profit, in dollars
+    pub max_drawdown: f64,
+}
+
+impl Summary {
+    pub fn new(positions: &[Position]) -> Self {
+        Self::from_returns(&closed_returns(positions))
+    }
+
+    pub fn from_returns(returns: &[f64]) -> Self {
+        let mut summary = Self::default();
+        for r in returns {
+            summary.trades += 1;
+            if *r >= 0.0 {
+                summary.wins += 1;
+                summary.gross_profit += r;
+            } else {
+                summary.gross_loss -= r;
+            }
+        }
+        summary.max_drawdown = max_drawdown(&cumulative(0.0, returns));
+        summary
+    }
+
+    pub fn net_profit(&self) -> f64 {
+        self.gross_profit - self.gross_loss
+    }
+
+    pub fn losses(&self) -> usize {
+        self.trades - self.wins
+    }
+
+    pub fn win_percent(&self) -> f64 {
+        if self.trades == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.trades as f64 * 100.0
+        }
+    }
+
+    // None without any losing trades.
+    pub fn profit_factor(&self) -> Option<f64> {
+        if self.gross_loss > 0.0 {
+            Some(self.gross_profit / self.gross_loss)
+        } else {
+            None
+        }
+    }
+
+    // Adds another run's trades, e.g. from another ticker. The drawdown is
+    // the worst of the two since the runs don't share an equity curve.
+    pub fn merge(&mut self, other: &Summary) {
+        self.trades += other.trades;
+        self.wins += other.wins;
+        self.gross_profit += other.gross_profit;
+        self.gross_loss += other.gross_loss;
+        self.max_drawdown = self.max_drawdown.max(other.max_drawdown);
+    }
+}
+
+// Averages over closed positions of the maximum adverse and favorable
+// excursions, in dollars. The edge ratio is the average MFE over the size of
+// the average MAE; above 1, trades tend to go further for the entry than
+// against it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Excursions {
+    pub average_mae: f64,
+    pub average_mfe: f64,
+    pub edge_ratio: Option<f64>,
+}
+
+impl Excursions {
+    pub fn new(positions: &[Position]) -> Self {
+        let closed: Vec<&Position> = positions.iter().filter(|p| !p.open).collect();
+        if closed.is_empty() {
+            return Self::default();
+        }
+        let count = closed.len() as f64;
+        let average_mae = closed.iter().map(|p| p.mae()).sum::<f64>() / count;
+        let average_mfe = closed.iter().map(|p| p.mfe()).sum::<f64>() / count;
+        Self {
+            average_mae,
+            average_mfe,
+            edge_ratio: if average_mae < 0.0 {
+                Some(average_mfe / -average_mae)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+// Returns of the closed positions, in the order they were closed.
+pub fn closed_returns(positions: &[Position]) -> Vec<f64> {
+    closed_trades(positions).iter().map(|(_, r)| *r).collect()
+}
+
+// Close times and returns of the closed positions, in the order they were closed.
+pub fn closed_trades(positions: &[Position]) -> Vec<(clock::LocalDateTime, f64)> {
+    let mut closed: Vec<(clock::LocalDateTime, f64)> = positions
+        .iter()
+        .filter(|p| !p.open)
+        .filter_map(|p| Some((p.closes.last()?.time, p.total_return())))
+        .collect();
+    closed.sort_by_key(|(time, _)| *time);
+    closed
+}
+
+// Running totals of returns, starting from start.
+pub fn cumulative(start: f64, returns: &[f64]) -> Vec<f64> {
+    let mut equity = vec![start];
+    for r in returns {
+        equity.push(equity[equity.len() - 1] + r);
+    }
+    equity
+}
+
+pub fn max_drawdown(equity: &[f64]) -> f64 {
+    let mut peak = f64::MIN;
+    let mut drawdown: f64 = 0.0;
+    for value in equity {
+        peak = peak.max(*value);
+        drawdown = drawdown.max(peak - value);
+    }
+    drawdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{max_drawdown, Excursions, Summary};
+    use crate::{apis::candles::Candle, clock, trading::Position};
+
+    #[test]
+    fn summary_counts_trades_and_drawdown() {
+        let summary = Summary::from_returns(&[10.0, -5.0, -10.0, 20.0, 0.0]);
+        assert_eq!(summary.trades, 5);
+        assert_eq!(summary.wins, 3);
+        assert_eq!(summary.net_profit(), 15.0);
+        assert_eq!(summary.profit_factor(), Some(2.0));
+        assert_eq!(summary.max_drawdown, 15.0);
+        assert_eq!(max_drawdown(&[100.0, 90.0, 120.0, 60.0]), 60.0);
+    }
+
+    #[test]
+    fn excursions_use_the_range_traded_through() {
+        let ticker = "ABC".to_string();
+        let time = clock::datetime(2020, 9, 29, 10, 0, 0);
+        let candle = Candle::new(10.0, 10.0, 11.0, 9.0, 100, time);
+
+        let mut long = Position::open(&ticker, 10, 10.0, time);
+        long.track_excursion(&candle);
+        long.close(10.5, time);
+        assert_eq!((long.mae(), long.mfe()), (-10.0, 10.0));
+
+        // a short gains as price falls
+        let mut short = Position::open(&ticker, -10, 9.5, time);
+        short.track_excursion(&candle);
+        short.close(9.5, time);
+        assert_eq!((short.mae(), short.mfe()), (-15.0, 5.0));
+
+        let excursions = Excursions::new(&[long, short]);
+        assert_eq!(excursions.average_mae, -12.5);
+        assert_eq!(excursions.average_mfe, 7.5);
+        assert_eq!(excursions.edge_ratio, Some(0.6));
+    }
+}
@@ -0,0 +1,180 @@
+use super::{metrics, random::Rng};
+
+const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resampling {
+    // draws as many trades as there were, with replacement
+    Bootstrap,
+    // the same trades in a different order
+    Shuffle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MonteCarloConfig {
+    pub runs: usize,
+    pub seed: u64,
+    pub starting_equity: f64,
+    // a run is ruined once equity falls this far below the starting equity
+    pub ruin_percent: f64,
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> Self {
+        Self {
+            runs: 1000,
+            seed: 1,
+            starting_equity: 1000.0,
+            ruin_percent: 50.0,
+        }
+    }
+}
+
+// Sorted outcomes of every run.
+#[derive(Debug, PartialEq)]
+pub struct Distribution {
+    values: Vec<f64>,
+}
+
+impl Distribution {
+    fn new(mut values: Vec<f64>) -> Self {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Self { values }
+    }
+
+    // The nearest ranked value, e.g. 50.0 for the median.
+    pub fn percentile(&self, percent: f64) -> f64 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let rank = (percent / 100.0 * (self.values.len() - 1) as f64).round() as usize;
+        self.values[rank.min(self.values.len() - 1)]
+    }
+}
+
+pub struct MonteCarlo {
+    pub final_equity: Distribution,
+    pub max_drawdown: Distribution,
+    // fraction of runs that were ruined
+    pub risk_of_ruin: f64,
+}
+
+pub fn simulate(returns: &[f64], resampling: Resampling, config: &MonteCarloConfig) -> MonteCarlo {
+    let mut rng = Rng::new(config.seed);
+    let ruin = config.starting_equity * (1.0 - config.ruin_percent / 100.0);
+    let mut final_equity = Vec::with_capacity(config.runs);
+    let mut max_drawdown = Vec::with_capacity(config.runs);
+    let mut ruined = 0;
+
+    let mut trades = returns.to_vec();
+    for _ in 0..config.runs {
+        match resampling {
+            Resampling::Bootstrap => {
+                for trade in trades.iter_mut() {
+                    *trade = returns[rng.below(returns.len())];
+                }
+            }
+            Resampling::Shuffle => rng.shuffle(&mut trades),
+        }
+
+        let equity = metrics::cumulative(config.starting_equity, &trades);
+        if equity.iter().any(|value| *value <= ruin) {
+            ruined += 1;
+        }
+        final_equity.push(equity[equity.len() - 1]);
+        max_drawdown.push(metrics::max_drawdown(&equity));
+    }
+
+    MonteCarlo {
+        final_equity: Distribution::new(final_equity),
+        max_drawdown: Distribution::new(max_drawdown),
+        risk_of_ruin: if config.runs == 0 {
+            0.0
+        } else {
+            ruined as f64 / config.runs as f64
+        },
+    }
+}
+
+// Prints percentile bands of both resamplings of a backtest's trade returns.
+pub fn log_results(ticker: &str, returns: &[f64], config: &MonteCarloConfig) {
+    if returns.is_empty() {
+        return;
+    }
+    println!(
+        "{:6}-- Monte Carlo: {} runs of {} trades, seed {}",
+        ticker,
+        config.runs,
+        returns.len(),
+        config.seed
+    );
+    print!("{:24}", "");
+    for percent in PERCENTILES.iter() {
+        print!(" {:>10}", format!("{}%", percent));
+    }
+    println!();
+
+    for resampling in [Resampling::Bootstrap, Resampling::Shuffle].iter() {
+        let result = simulate(returns, *resampling, config);
+        let name = format!("{:?}", resampling);
+        for (label, distribution) in [
+            ("ending equity", &result.final_equity),
+            ("max drawdown", &result.max_drawdown),
+        ]
+        .iter()
+        {
+            print!("{:24}", format!("{} {}", name, label));
+            for percent in PERCENTILES.iter() {
+                print!(" {:>10.2}", distribution.percentile(*percent));
+            }
+            println!();
+        }
+        println!(
+            "{} risk of ruin ({}% drawdown): {:.2}%",
+            name,
+            config.ruin_percent,
+            result.risk_of_ruin * 100.0
+        );
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulate, MonteCarloConfig, Resampling};
+
+    const RETURNS: [f64; 6] = [50.0, -30.0, 20.0, -60.0, 40.0, 10.0];
+
+    #[test]
+    fn shuffles_keep_the_ending_equity_but_move_the_drawdown() {
+        let config = MonteCarloConfig {
+            runs: 200,
+            ..MonteCarloConfig::default()
+        };
+        let result = simulate(&RETURNS, Resampling::Shuffle, &config);
+        assert_eq!(result.final_equity.percentile(5.0), 1030.0);
+        assert_eq!(result.final_equity.percentile(95.0), 1030.0);
+        // the two losses back to back, or the smaller one alone
+        assert_eq!(result.max_drawdown.percentile(100.0), 90.0);
+        assert_eq!(result.max_drawdown.percentile(0.0), 60.0);
+        assert_eq!(result.risk_of_ruin, 0.0);
+    }
+
+    #[test]
+    fn bootstrap_is_repeatable_and_counts_ruin() {
+        let config = MonteCarloConfig {
+            runs: 500,
+            ruin_percent: 10.0,
+            ..MonteCarloConfig::default()
+        };
+        let result = simulate(&RETURNS, Resampling::Bootstrap, &config);
+        let again = simulate(&RETURNS, Resampling::Bootstrap, &config);
+        assert_eq!(result.final_equity, again.final_equity);
+        assert_eq!(result.risk_of_ruin, again.risk_of_ruin);
+
+        let low = result.final_equity.percentile(5.0);
+        let high = result.final_equity.percentile(95.0);
+        assert!(low < 1030.0 && 1030.0 < high);
+        assert!(result.risk_of_ruin > 0.0 && result.risk_of_ruin < 1.0);
+    }
+}
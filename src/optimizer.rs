@@ -0,0 +1,360 @@
+use super::{
+    apis::{alpha_vantage, candles::Candle},
+    backtest::AccountSettings,
+    config, engine,
+    metrics::Summary,
+    random::Rng,
+    strategies::{Sma9CrossesSma180, SmaCrossConfig},
+    trading::{self, Holding, Position, PriceData},
+};
+use std::thread;
+
+// Parameter values from start to end inclusive, e.g. "5:20:5" or just "9".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub start: usize,
+    pub end: usize,
+    pub step: usize,
+}
+
+impl Range {
+    pub fn single(value: usize) -> Self {
+        Self {
+            start: value,
+            end: value,
+            step: 1,
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let error = || format!("invalid range: {}", spec);
+        let parts = spec
+            .split(':')
+            .map(|p| p.parse::<usize>().map_err(|_| error()))
+            .collect::<Result<Vec<usize>, String>>()?;
+        let range = match parts.as_slice() {
+            [value] => Self::single(*value),
+            [start, end] => Self {
+                start: *start,
+                end: *end,
+                step: 1,
+            },
+            [start, end, step] => Self {
+                start: *start,
+                end: *end,
+                step: *step,
+            },
+            _ => return Err(error()),
+        };
+        if range.step == 0 || range.start == 0 || range.start > range.end {
+            return Err(error());
+        }
+        Ok(range)
+    }
+
+    pub fn values(&self) -> Vec<usize> {
+        (self.start..=self.end).step_by(self.step).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Search {
+    Grid,
+    // a sample of the grid without repeats, reproducible from the seed
+    Random { samples: usize, seed: u64 },
+}
+
+impl Search {
+    // How the parameter sets were picked, for report headers.
+    pub fn describe(&self) -> String {
+        match self {
+            Search::Grid => "the full grid".to_string(),
+            Search::Random { samples, seed } => {
+                format!("a random sample of {}, seed {}", samples, seed)
+            }
+        }
+    }
+}
+
+// Every fast/slow combination with the fast SMA shorter than the slow one.
+pub fn parameter_sets(fast: &Range, slow: &Range, search: Search) -> Vec<SmaCrossConfig> {
+    let mut sets = Vec::new();
+    for fast_bars in fast.values() {
+        for slow_bars in slow.values() {
+            if fast_bars < slow_bars {
+                sets.push(SmaCrossConfig {
+                    fast_bars,
+                    slow_bars,
+                });
+            }
+        }
+    }
+
+    if let Search::Random { samples, seed } = search {
+        Rng::new(seed).shuffle(&mut sets);
+        sets.truncate(samples);
+    }
+    sets
+}
+
+pub struct SweepResult {
+    pub config: SmaCrossConfig,
+    pub summary: Summary,
+}
+
+// Every set warms up on the longest slow SMA so they all trade the same candles.
+fn warmup(configs: &[SmaCrossConfig]) -> usize {
+    configs
+        .iter()
+        .map(|config| config.slow_bars)
+        .max()
+        .unwrap_or(0)
+}
+
+// Daily bars only go back as far as the price history, so a swing sweep
+// warming up on more of them than that would have nothing left to trade.
+pub fn check_history(configs: &[SmaCrossConfig], holding: &Holding) -> Result<(), String> {
+    let warmup = warmup(configs);
+    match holding {
+        Holding::Swing(_) if warmup >= trading::HISTORY_DAILY_BARS => Err(format!(
+            "a {} day slow SMA needs more than the {} daily bars of price history, \
+             use a --slow range below it with --swing",
+            warmup,
+            trading::HISTORY_DAILY_BARS
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Backtests every parameter set on every ticker, spread across the CPU cores,
+// and returns the results ranked by net profit.
+pub fn sweep(
+    candles: &[(String, Vec<Candle>)],
+    configs: &[SmaCrossConfig],
+    settings: &AccountSettings,
+) -> Vec<SweepResult> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = configs.len().div_ceil(threads).max(1);
+    let warmup = warmup(configs);
+
+    let mut results: Vec<SweepResult> = thread::scope(|scope| {
+        let handles: Vec<_> = configs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|config| evaluate(candles, *config, warmup, settings))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    results.sort_by(|a, b| {
+        b.summary
+            .net_profit()
+            .partial_cmp(&a.summary.net_profit())
+            .unwrap()
+    });
+    results
+}
+
+fn evaluate(
+    candles: &[(String, Vec<Candle>)],
+    config: SmaCrossConfig,
+    warmup: usize,
+    settings: &AccountSettings,
+) -> SweepResult {
+    let mut summary = Summary::default();
+    for (ticker, candles) in candles {
+        if let Some(positions) = backtest(ticker, candles.clone(), config, warmup, settings) {
+            summary.merge(&Summary::new(&positions));
+        }
+    }
+    SweepResult { config, summary }
+}
+
+// Runs the crossover over candles, the first warmup bars of which are history.
+// warmup should be at least the config's slow_bars. None without enough
+// candles for the history.
+pub fn backtest<'t>(
+    ticker: &'t String,
+    candles: Vec<Candle>,
+    config: SmaCrossConfig,
+    warmup: usize,
+    settings: &AccountSettings,
+) -> Option<Vec<Position<'t>>> {
+    let mut account = settings.account();
+    let mut price_data = PriceData::with_candles(candles);
+    let history = match settings.holding {
+        Holding::Intraday => price_data.history(ticker, warmup, "1:minute"),
+        Holding::Swing(_) => price_data.daily_history(ticker, warmup),
+    }?;
+
+    let mut strategy = Sma9CrossesSma180::with_config(history, config);
+    engine::run_strategy(
+        ticker,
+        &mut strategy,
+        &mut price_data,
+        &mut account,
+        settings.engine,
+    );
+    Some(account.positions)
+}
+
+// Minute candles for every ticker that has any.
+pub fn load_candles(tickers: &[String], env: &config::Env) -> Vec<(String, Vec<Candle>)> {
+    let client = alpha_vantage::client(env);
+    let mut candles = Vec::new();
+    for ticker in tickers {
+        match trading::fetch_candles(&client, ticker, "1:minute") {
+            Some(data) => candles.push((ticker.clone(), data)),
+            None => eprintln!("{}: no price data", ticker),
+        }
+    }
+    candles
+}
+
+pub fn run_optimizer(
+    tickers: &[String],
+    env: &config::Env,
+    configs: &[SmaCrossConfig],
+    search: Search,
+    settings: &AccountSettings,
+) {
+    let candles = load_candles(tickers, env);
+    println!(
+        "Optimizing SMA crossover over {} parameter sets from {}",
+        configs.len(),
+        search.describe()
+    );
+    println!(
+        "{:>4} {:>5} {:>5} {:>7} {:>8} {:>11} {:>6} {:>10}",
+        "Rank", "Fast", "Slow", "Trades", "Win%", "Net", "PF", "Max DD"
+    );
+    for (rank, result) in sweep(&candles, configs, settings).iter().enumerate() {
+        let summary = &result.summary;
+        let profit_factor = match summary.profit_factor() {
+            Some(factor) => format!("{:.2}", factor),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:>4} {:>5} {:>5} {:>7} {:>7.2}% {:>11.4} {:>6} {:>10.4}",
+            rank + 1,
+            result.config.fast_bars,
+            result.config.slow_bars,
+            summary.trades,
+            summary.win_percent(),
+            summary.net_profit(),
+            profit_factor,
+            summary.max_drawdown,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backtest, check_history, parameter_sets, sweep, Range, Search};
+    use crate::{
+        apis::candles::Candle,
+        backtest::AccountSettings,
+        clock,
+        strategies::SmaCrossConfig,
+        trading::{Holding, SwingConfig},
+    };
+
+    fn sine_candles() -> Vec<Candle> {
+        let start = clock::datetime(2020, 9, 29, 9, 30, 0);
+        (0..120)
+            .map(|minute| {
+                let close = 10.0 + ((minute as f64) / 6.0).sin();
+                let open = close - 0.05 * ((minute % 3) as f64 - 1.0);
+                let time = start + clock::Duration::minutes(minute);
+                Candle::new(open, close, close.max(open), close.min(open), 100, time)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranges_are_parsed_and_combined_into_parameter_sets() {
+        assert_eq!(
+            Range::parse("5:20:5").unwrap().values(),
+            vec![5, 10, 15, 20]
+        );
+        assert_eq!(Range::parse("9").unwrap().values(), vec![9]);
+        assert!(Range::parse("20:5").is_err());
+        assert!(Range::parse("5:20:0").is_err());
+
+        let fast = Range::parse("5:15:5").unwrap();
+        let slow = Range::parse("10:20:10").unwrap();
+        assert_eq!(parameter_sets(&fast, &slow, Search::Grid).len(), 4);
+
+        let random = Search::Random {
+            samples: 3,
+            seed: 1,
+        };
+        let sample = parameter_sets(&fast, &slow, random);
+        assert_eq!(sample.len(), 3);
+        assert_eq!(sample, parameter_sets(&fast, &slow, random));
+    }
+
+    #[test]
+    fn sweep_ranks_every_parameter_set() {
+        let data = vec![("ABC".to_string(), sine_candles())];
+        let configs = parameter_sets(
+            &Range::parse("2:4").unwrap(),
+            &Range::parse("5:10:5").unwrap(),
+            Search::Grid,
+        );
+
+        let results = sweep(&data, &configs, &AccountSettings::default());
+        assert_eq!(results.len(), 6);
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].summary.net_profit() >= pair[1].summary.net_profit()));
+        assert!(results.iter().any(|result| result.summary.trades > 0));
+    }
+
+    #[test]
+    fn every_parameter_set_trades_after_the_same_warmup() {
+        let ticker = "ABC".to_string();
+        let candles = sine_candles();
+        let config = SmaCrossConfig {
+            fast_bars: 2,
+            slow_bars: 5,
+        };
+        let settings = AccountSettings::default();
+
+        let own = backtest(&ticker, candles.clone(), config, 5, &settings).unwrap();
+        assert!(own[0].time < candles[40].datetime);
+        let shared = backtest(&ticker, candles.clone(), config, 40, &settings).unwrap();
+        assert!(!shared.is_empty());
+        assert!(shared
+            .iter()
+            .all(|position| position.time > candles[40].datetime));
+    }
+
+    #[test]
+    fn swing_sweeps_must_warm_up_within_the_daily_history() {
+        let default = parameter_sets(
+            &Range::parse("5:15:2").unwrap(),
+            &Range::parse("60:240:30").unwrap(),
+            Search::Grid,
+        );
+        let swing = Holding::Swing(SwingConfig::default());
+        assert!(check_history(&default, &Holding::Intraday).is_ok());
+        assert!(check_history(&default, &swing).is_err());
+
+        let short = parameter_sets(
+            &Range::parse("5").unwrap(),
+            &Range::parse("20:40:10").unwrap(),
+            Search::Grid,
+        );
+        assert!(check_history(&short, &swing).is_ok());
+    }
+}
@@ -0,0 +1,314 @@
+use super::{
+    apis::candles::Candle,
+    metrics::{Excursions, Summary},
+    trading::Position,
+};
+use serde::Serialize;
+use std::{fs, path::Path};
+
+pub const DIRECTORY: &str = "results";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("invalid output format: {}", format)),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+// Rows that can also be written as CSV, in the same order as their fields.
+trait CsvRecord {
+    const HEADER: &'static str;
+    fn row(&self) -> String;
+}
+
+// A closed position. Exits in several parts are averaged into one price.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Trade {
+    pub ticker: String,
+    pub entry_time: String,
+    pub entry_price: f64,
+    pub exit_time: String,
+    pub exit_price: f64,
+    // negative for shorts
+    pub shares: i32,
+    pub fees: f64,
+    pub profit_and_loss: f64,
+    // worst (zero or less) and best unrealized profit while open, in dollars
+    pub mae: f64,
+    pub mfe: f64,
+}
+
+impl CsvRecord for Trade {
+    const HEADER: &'static str =
+        "ticker,entry_time,entry_price,exit_time,exit_price,shares,fees,profit_and_loss,mae,mfe";
+
+    fn row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.ticker,
+            self.entry_time,
+            self.entry_price,
+            self.exit_time,
+            self.exit_price,
+            self.shares,
+            self.fees,
+            self.profit_and_loss,
+            self.mae,
+            self.mfe
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EquityPoint {
+    pub time: String,
+    pub equity: f64,
+}
+
+impl CsvRecord for EquityPoint {
+    const HEADER: &'static str = "time,equity";
+
+    fn row(&self) -> String {
+        format!("{},{}", self.time, self.equity)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Metrics {
+    pub ticker: String,
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub win_percent: f64,
+    pub gross_profit: f64,
+    pub gross_loss: f64,
+    pub net_profit: f64,
+    pub profit_factor: Option<f64>,
+    pub max_drawdown: f64,
+    pub ending_equity: f64,
+    pub average_mae: f64,
+    pub average_mfe: f64,
+    pub edge_ratio: Option<f64>,
+}
+
+impl CsvRecord for Metrics {
+    const HEADER: &'static str = "ticker,trades,wins,losses,win_percent,gross_profit,gross_loss,\
+                                  net_profit,profit_factor,max_drawdown,ending_equity,average_mae,\
+                                  average_mfe,edge_ratio";
+
+    fn row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.ticker,
+            self.trades,
+            self.wins,
+            self.losses,
+            self.win_percent,
+            self.gross_profit,
+            self.gross_loss,
+            self.net_profit,
+            self.profit_factor.map_or(String::new(), |f| f.to_string()),
+            self.max_drawdown,
+            self.ending_equity,
+            self.average_mae,
+            self.average_mfe,
+            self.edge_ratio.map_or(String::new(), |r| r.to_string())
+        )
+    }
+}
+
+// Closed positions in the order they were opened. commission is charged on
+// every order, opening and closing.
+pub fn blotter(positions: &[Position], commission: f64) -> Vec<Trade> {
+    positions
+        .iter()
+        .filter(|position| !position.open)
+        .map(|position| {
+            let closed_shares: i32 = position.closes.iter().map(|close| close.shares).sum();
+            let proceeds: f64 = position
+                .closes
+                .iter()
+                .map(|close| close.ask * close.shares as f64)
+                .sum();
+            let exit_time = position.closes.last().map(|close| close.time).unwrap();
+            let fees = commission * (1 + position.closes.len()) as f64;
+            Trade {
+                ticker: position.ticker.to_string(),
+                entry_time: position.time.to_rfc3339(),
+                entry_price: position.bid,
+                exit_time: exit_time.to_rfc3339(),
+                exit_price: proceeds / closed_shares as f64,
+                shares: position.shares,
+                fees,
+                profit_and_loss: position.total_return() - fees,
+                mae: position.mae(),
+                mfe: position.mfe(),
+            }
+        })
+        .collect()
+}
+
+// Starting capital plus realized profit and open positions marked at each
+// candle's close.
+pub fn equity_curve(positions: &[Position], candles: &[Candle], capital: f64) -> Vec<EquityPoint> {
+    candles
+        .iter()
+        .map(|candle| {
+            let time = candle.datetime;
+            let value: f64 = positions
+                .iter()
+                .filter(|position| position.time <= time)
+                .map(|position| {
+                    let closes = position.closes.iter().filter(|close| close.time <= time);
+                    let (closed, realized) = closes.fold((0, 0.0), |(shares, profit), close| {
+                        let gain = (close.ask - position.bid) * close.shares as f64;
+                        (shares + close.shares, profit + gain)
+                    });
+                    let remaining = (position.shares - closed) as f64;
+                    realized + (candle.close - position.bid) * remaining
+                })
+                .sum();
+            EquityPoint {
+                time: time.to_rfc3339(),
+                equity: capital + value,
+            }
+        })
+        .collect()
+}
+
+pub fn metrics(ticker: &str, positions: &[Position], trades: &[Trade], capital: f64) -> Metrics {
+    let returns: Vec<f64> = trades.iter().map(|trade| trade.profit_and_loss).collect();
+    let summary = Summary::from_returns(&returns);
+    let excursions = Excursions::new(positions);
+    Metrics {
+        ticker: ticker.to_string(),
+        trades: summary.trades,
+        wins: summary.wins,
+        losses: summary.losses(),
+        win_percent: summary.win_percent(),
+        gross_profit: summary.gross_profit,
+        gross_loss: summary.gross_loss,
+        net_profit: summary.net_profit(),
+        profit_factor: summary.profit_factor(),
+        max_drawdown: summary.max_drawdown,
+        ending_equity: capital + summary.net_profit(),
+        average_mae: excursions.average_mae,
+        average_mfe: excursions.average_mfe,
+        edge_ratio: excursions.edge_ratio,
+    }
+}
+
+// Writes <ticker>-trades, <ticker>-equity and <ticker>-summary files to the
+// results directory.
+pub fn write(
+    ticker: &str,
+    positions: &[Position],
+    candles: &[Candle],
+    capital: f64,
+    format: Format,
+) -> Result<(), String> {
+    let directory = Path::new(DIRECTORY);
+    fs::create_dir_all(directory)
+        .map_err(|err| format!("couldn't create {}: {}", DIRECTORY, err))?;
+
+    let trades = blotter(positions, 0.0);
+    let summary = metrics(ticker, positions, &trades, capital);
+    let equity = equity_curve(positions, candles, capital);
+
+    let path = |name: &str| directory.join(format!("{}-{}.{}", ticker, name, format.extension()));
+    write_records(&path("trades"), &trades, format)?;
+    write_records(&path("equity"), &equity, format)?;
+    write_records(&path("summary"), std::slice::from_ref(&summary), format)
+}
+
+fn write_records<R: CsvRecord + Serialize>(
+    path: &Path,
+    records: &[R],
+    format: Format,
+) -> Result<(), String> {
+    let contents = match format {
+        Format::Json => serde_json::to_string_pretty(records).map_err(|err| err.to_string())?,
+        Format::Csv => {
+            let mut lines = vec![R::HEADER.to_string()];
+            lines.extend(records.iter().map(CsvRecord::row));
+            lines.join("\n") + "\n"
+        }
+    };
+    fs::write(path, contents).map_err(|err| format!("couldn't write {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blotter, equity_curve, metrics, CsvRecord, Format, Metrics, Trade};
+    use crate::{apis::candles::candle, trading::Position};
+
+    #[test]
+    fn blotter_and_equity_curve_follow_the_position() {
+        let ticker = "ABC".to_string();
+        let candles = vec![
+            candle(10.0, 10.0, 10.0, 10.0, 100, 0),
+            candle(9.8, 9.8, 10.2, 9.5, 100, 1),
+            candle(10.8, 10.8, 11.0, 9.9, 100, 2),
+            candle(10.8, 10.8, 10.8, 10.8, 100, 3),
+        ];
+        let mut position = Position::open(&ticker, 10, 10.0, candles[0].datetime);
+        position.track_excursion(&candles[1]);
+        position.track_excursion(&candles[2]);
+        position.close(10.8, candles[2].datetime);
+        let positions = vec![position];
+
+        let trades = blotter(&positions, 0.01);
+        assert_eq!(trades.len(), 1);
+        let trade = &trades[0];
+        assert_eq!(trade.exit_price, 10.8);
+        assert!((trade.profit_and_loss - 7.98).abs() < 1e-9);
+        assert!((trade.mae + 5.0).abs() < 1e-9);
+        assert!((trade.mfe - 10.0).abs() < 1e-9);
+        assert_eq!(
+            trade.row().split(',').count(),
+            Trade::HEADER.split(',').count()
+        );
+
+        let equity: Vec<f64> = equity_curve(&positions, &candles, 1000.0)
+            .iter()
+            .map(|point| point.equity)
+            .collect();
+        let expected = [1000.0, 998.0, 1008.0, 1008.0];
+        assert!(equity
+            .iter()
+            .zip(&expected)
+            .all(|(e, x)| (e - x).abs() < 1e-9));
+
+        let summary = metrics("ABC", &positions, &trades, 1000.0);
+        assert_eq!(summary.trades, 1);
+        assert!((summary.ending_equity - 1007.98).abs() < 1e-9);
+        assert!((summary.edge_ratio.unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(
+            summary.row().split(',').count(),
+            Metrics::HEADER.split(',').count()
+        );
+    }
+
+    #[test]
+    fn formats_are_parsed() {
+        assert_eq!(Format::parse("JSON"), Ok(Format::Json));
+        assert_eq!(Format::parse("csv"), Ok(Format::Csv));
+        assert!(Format::parse("xml").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use super::{
+    apis::alpha_vantage,
+    clock, config,
+    engine::{Engine, EngineConfig},
+    pdt::DayTrades,
+    simulation::{self, SimBroker},
+    strategies,
+    trading::Account,
+};
+use std::{thread, time::Duration};
+
+// How often the latest candles are requested.
+const POLL_SECONDS: u64 = 60;
+
+// Paper trades the SMA crossover on a simulated cash account until the market
+// closes. New candles are queued as each poll returns them and the engine runs
+// up to the clock, so fills given a latency wait until it has passed.
+pub fn run_paper(ticker: &String, env: &config::Env) {
+    let client = alpha_vantage::client(env);
+    let candles = match client.latest_candles(ticker) {
+        Some(candles) if candles.len() >= 9 => candles,
+        _ => {
+            eprintln!("{}: no price data", ticker);
+            return;
+        }
+    };
+    let mut seen = candles.last().unwrap().datetime;
+    println!("Paper trading {} from {}", ticker, seen);
+
+    let mut strategy = strategies::SmaCrossover::new(&candles);
+    let mut account = Account::new(SimBroker::new());
+    account.day_trades = DayTrades::enforced();
+    let mut engine = Engine::new(EngineConfig::default());
+    engine.add_strategy(ticker, &mut strategy);
+
+    let close = clock::Time::from_hms(16, 0, 0);
+    let mut logged = 0;
+    while clock::now().time() < close {
+        thread::sleep(Duration::from_secs(POLL_SECONDS));
+        for candle in client.latest_candles(ticker).unwrap_or_default() {
+            if candle.datetime > seen {
+                seen = candle.datetime;
+                engine.market_data(ticker, candle);
+            }
+        }
+        engine.run_until(clock::now(), &mut account);
+
+        for position in &account.positions[logged..] {
+            println!(
+                "Opened {}: {} @ ${:.2} - {}",
+                position.ticker, position.shares, position.bid, position.time
+            );
+        }
+        logged = account.positions.len();
+    }
+    simulation::log_results(account);
+}
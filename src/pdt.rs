@@ -0,0 +1,131 @@
+use super::clock;
+use std::fmt;
+
+// FINRA's pattern day trader rule: four or more day trades within five business
+// days in a margin account with less than $25,000 of equity.
+pub const MIN_EQUITY: f64 = 25_000.0;
+const MAX_DAY_TRADES: usize = 3;
+const WINDOW_DAYS: i64 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PdtFlag {
+    // a fourth day trade in the window while under the minimum equity
+    PatternDayTrader {
+        time: clock::LocalDateTime,
+    },
+    // an opening order refused because it could become a fourth day trade
+    Blocked {
+        ticker: String,
+        time: clock::LocalDateTime,
+    },
+}
+
+impl fmt::Display for PdtFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PdtFlag::PatternDayTrader { time } => {
+                write!(f, "{} - flagged as a pattern day trader", time)
+            }
+            PdtFlag::Blocked { ticker, time } => {
+                write!(f, "{} - {} blocked, day trade limit reached", time, ticker)
+            }
+        }
+    }
+}
+
+// Day trades are positions opened and closed on the same day. They're always
+// counted; when enforced, new positions are refused once three day trades fall
+// in the window, since any of them could become a fourth.
+#[derive(Default)]
+pub struct DayTrades {
+    pub enforce: bool,
+    pub flags: Vec<PdtFlag>,
+    dates: Vec<clock::LocalDate>,
+}
+
+impl DayTrades {
+    pub fn enforced() -> Self {
+        Self {
+            enforce: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.dates.len()
+    }
+
+    // Day trades in the five business days ending on date.
+    pub fn in_window(&self, date: clock::LocalDate) -> usize {
+        self.dates
+            .iter()
+            .filter(|day| **day <= date && clock::business_days_between(**day, date) < WINDOW_DAYS)
+            .count()
+    }
+
+    pub fn record(&mut self, time: clock::LocalDateTime, equity: f64) {
+        self.dates.push(time.date());
+        if equity < MIN_EQUITY && self.in_window(time.date()) > MAX_DAY_TRADES {
+            self.flags.push(PdtFlag::PatternDayTrader { time });
+        }
+    }
+
+    pub fn allows_open(&mut self, ticker: &str, time: clock::LocalDateTime, equity: f64) -> bool {
+        if !self.enforce || equity >= MIN_EQUITY || self.in_window(time.date()) < MAX_DAY_TRADES {
+            return true;
+        }
+        self.flags.push(PdtFlag::Blocked {
+            ticker: ticker.to_string(),
+            time,
+        });
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DayTrades, PdtFlag};
+    use crate::clock;
+
+    #[test]
+    fn day_trades_roll_off_after_five_business_days() {
+        let mut day_trades = DayTrades::enforced();
+        // Thursday, Friday and the next Monday
+        day_trades.record(clock::datetime(2020, 9, 24, 10, 0, 0), 1000.0);
+        day_trades.record(clock::datetime(2020, 9, 25, 10, 0, 0), 1000.0);
+        day_trades.record(clock::datetime(2020, 9, 28, 10, 0, 0), 1000.0);
+
+        let wednesday = clock::datetime(2020, 9, 30, 10, 0, 0);
+        assert_eq!(day_trades.in_window(wednesday.date()), 3);
+        assert!(!day_trades.allows_open("ABC", wednesday, 1000.0));
+        assert!(day_trades.allows_open("ABC", wednesday, 25_000.0));
+
+        let next_thursday = clock::datetime(2020, 10, 1, 10, 0, 0);
+        assert_eq!(day_trades.in_window(next_thursday.date()), 2);
+        assert!(day_trades.allows_open("ABC", next_thursday, 1000.0));
+        assert_eq!(
+            day_trades.flags,
+            vec![PdtFlag::Blocked {
+                ticker: "ABC".to_string(),
+                time: wednesday
+            }]
+        );
+    }
+
+    #[test]
+    fn fourth_day_trade_is_flagged_when_not_enforced() {
+        let mut day_trades = DayTrades::default();
+        for hour in 10..14 {
+            let time = clock::datetime(2020, 9, 29, hour, 0, 0);
+            assert!(day_trades.allows_open("ABC", time, 1000.0));
+            day_trades.record(time, 1000.0);
+        }
+        assert_eq!(day_trades.total(), 4);
+        assert_eq!(
+            day_trades.flags,
+            vec![PdtFlag::PatternDayTrader {
+                time: clock::datetime(2020, 9, 29, 13, 0, 0)
+            }]
+        );
+    }
+}
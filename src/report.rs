@@ -0,0 +1,389 @@
+use super::{
+    apis::candles::Candle,
+    output::{self, Trade},
+    trading::Position,
+};
+use chrono::Datelike;
+use std::{fmt::Write, fs, path::Path};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 240.0;
+const MARGIN: f64 = 60.0;
+// lines are thinned to about this many points to keep the file small
+const MAX_POINTS: usize = 1000;
+const BINS: usize = 12;
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// One HTML page for a backtest run, a section per ticker. Charts are inline
+// SVG and there's no script, so the page works offline.
+#[derive(Default)]
+pub struct Report {
+    sections: Vec<String>,
+}
+
+impl Report {
+    pub fn add(&mut self, ticker: &str, positions: &[Position], candles: &[Candle], capital: f64) {
+        let trades = output::blotter(positions, 0.0);
+        let summary = output::metrics(ticker, positions, &trades, capital);
+        let equity: Vec<f64> = output::equity_curve(positions, candles, capital)
+            .iter()
+            .map(|point| point.equity)
+            .collect();
+        let drawdown = drawdowns(&equity);
+        let ratio = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.2}", v));
+
+        let mut section = format!(
+            "<section>\n<h2>{}</h2>\n<p>Trades: {} - W%: {:.2}% - Net: ${:.2} - PF: {} - Max DD: ${:.2}</p>\n\
+             <p>MAE/MFE: ${:.2}/${:.2} - Edge ratio: {}</p>\n",
+            ticker,
+            summary.trades,
+            summary.win_percent,
+            summary.net_profit,
+            ratio(summary.profit_factor),
+            summary.max_drawdown,
+            summary.average_mae,
+            summary.average_mfe,
+            ratio(summary.edge_ratio)
+        );
+        section += &chart("Equity", &line(&equity, "#2a6fdb"));
+        section += &chart("Drawdown", &line(&drawdown, "#d0342c"));
+        section += &heatmap(&monthly_returns(candles, &equity));
+        section += &chart("Trade P/L", &histogram(&trades));
+        section += &chart("Price", &price(candles, positions));
+        section += "</section>\n";
+        self.sections.push(section);
+    }
+
+    pub fn html(&self) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Backtest</title>\n\
+             <style>body {{ font-family: sans-serif; margin: 2em; }} \
+             svg {{ display: block; margin-bottom: 1em; }} \
+             text {{ font-size: 11px; }}</style>\n</head>\n<body>\n<h1>Backtest</h1>\n{}</body>\n</html>\n",
+            self.sections.concat()
+        )
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(|err| format!("couldn't create {}: {}", directory.display(), err))?;
+        }
+        fs::write(path, self.html())
+            .map_err(|err| format!("couldn't write {}: {}", path.display(), err))
+    }
+}
+
+// Distance below the running peak at each point, zero or less.
+fn drawdowns(equity: &[f64]) -> Vec<f64> {
+    let mut peak = f64::MIN;
+    equity
+        .iter()
+        .map(|value| {
+            peak = peak.max(*value);
+            value - peak
+        })
+        .collect()
+}
+
+// Each month's return from the previous month's last equity, or the first
+// equity for the first month.
+fn monthly_returns(candles: &[Candle], equity: &[f64]) -> Vec<(i32, u32, f64)> {
+    let mut months: Vec<(i32, u32, f64)> = Vec::new();
+    let mut start = match equity.first() {
+        Some(first) => *first,
+        None => return months,
+    };
+    let mut month_end = start;
+    for (candle, value) in candles.iter().zip(equity) {
+        let (year, month) = (candle.datetime.year(), candle.datetime.month());
+        match months.last_mut() {
+            Some(last) if (last.0, last.1) == (year, month) => {}
+            Some(_) => {
+                start = month_end;
+                months.push((year, month, 0.0));
+            }
+            None => months.push((year, month, 0.0)),
+        }
+        month_end = *value;
+        months.last_mut().unwrap().2 = month_end / start - 1.0;
+    }
+    months
+}
+
+fn chart(title: &str, body: &str) -> String {
+    format!(
+        "<h3>{}</h3>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n{}</svg>\n",
+        title,
+        WIDTH + 2.0 * MARGIN,
+        HEIGHT + 2.0 * MARGIN,
+        body
+    )
+}
+
+// Maps values onto the chart area, with the range labelled on the left.
+struct Scale {
+    min: f64,
+    max: f64,
+    count: usize,
+}
+
+impl Scale {
+    fn new(values: &[f64], count: usize) -> Self {
+        let min = values.iter().cloned().fold(f64::MAX, f64::min);
+        let max = values.iter().cloned().fold(f64::MIN, f64::max);
+        let (min, max) = if min < max {
+            (min, max)
+        } else {
+            (min - 1.0, min + 1.0)
+        };
+        Self { min, max, count }
+    }
+
+    fn x(&self, index: usize) -> f64 {
+        MARGIN + index as f64 * WIDTH / (self.count.max(2) - 1) as f64
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        MARGIN + (self.max - value) / (self.max - self.min) * HEIGHT
+    }
+
+    fn labels(&self) -> String {
+        format!(
+            "<text x=\"0\" y=\"{:.1}\">{:.2}</text>\n<text x=\"0\" y=\"{:.1}\">{:.2}</text>\n",
+            self.y(self.max) + 4.0,
+            self.max,
+            self.y(self.min) + 4.0,
+            self.min
+        )
+    }
+
+    fn polyline(&self, values: &[f64], color: &str) -> String {
+        let step = values.len().div_ceil(MAX_POINTS).max(1);
+        let mut points = String::new();
+        for (index, value) in values.iter().enumerate() {
+            if index % step == 0 || index == values.len() - 1 {
+                write!(points, "{:.1},{:.1} ", self.x(index), self.y(*value)).unwrap();
+            }
+        }
+        format!(
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+            color,
+            points.trim_end()
+        )
+    }
+}
+
+fn line(values: &[f64], color: &str) -> String {
+    if values.is_empty() {
+        return String::new();
+    }
+    let scale = Scale::new(values, values.len());
+    scale.labels() + scale.polyline(values, color).as_str()
+}
+
+// Months across, a row per year, green for gains and red for losses.
+fn heatmap(months: &[(i32, u32, f64)]) -> String {
+    let mut years: Vec<i32> = months.iter().map(|(year, _, _)| *year).collect();
+    years.dedup();
+    let largest = months
+        .iter()
+        .map(|(_, _, r)| r.abs())
+        .fold(0.0, f64::max)
+        .max(f64::EPSILON);
+    let cell = WIDTH / 12.0;
+
+    let mut body = String::new();
+    for (column, name) in MONTHS.iter().enumerate() {
+        let x = MARGIN + column as f64 * cell + cell / 2.0;
+        writeln!(
+            body,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            x,
+            MARGIN - 8.0,
+            name
+        )
+        .unwrap();
+    }
+    for (row, year) in years.iter().enumerate() {
+        let y = MARGIN + row as f64 * 30.0;
+        writeln!(body, "<text x=\"0\" y=\"{:.1}\">{}</text>", y + 19.0, year).unwrap();
+        for (_, month, r) in months.iter().filter(|(y, _, _)| y == year) {
+            let x = MARGIN + (*month - 1) as f64 * cell;
+            let (red, green) = if *r < 0.0 { (208, 52) } else { (42, 160) };
+            writeln!(
+                body,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"28\" fill=\"rgb({},{},80)\" fill-opacity=\"{:.2}\"/>\n\
+                 <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:.2}%</text>",
+                x,
+                y,
+                cell - 2.0,
+                red,
+                green,
+                0.15 + 0.85 * r.abs() / largest,
+                x + cell / 2.0,
+                y + 19.0,
+                r * 100.0
+            )
+            .unwrap();
+        }
+    }
+    format!(
+        "<h3>Monthly returns</h3>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n{}</svg>\n",
+        WIDTH + 2.0 * MARGIN,
+        MARGIN + years.len() as f64 * 30.0 + 10.0,
+        body
+    )
+}
+
+// Counts of trades in equal width P/L bins.
+fn bins(profits: &[f64]) -> Vec<(f64, usize)> {
+    if profits.is_empty() {
+        return Vec::new();
+    }
+    let scale = Scale::new(profits, BINS);
+    let width = (scale.max - scale.min) / BINS as f64;
+    let mut counts = vec![0; BINS];
+    for profit in profits {
+        let bin = ((profit - scale.min) / width) as usize;
+        counts[bin.min(BINS - 1)] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(bin, count)| (scale.min + (bin as f64 + 0.5) * width, count))
+        .collect()
+}
+
+fn histogram(trades: &[Trade]) -> String {
+    let profits: Vec<f64> = trades.iter().map(|trade| trade.profit_and_loss).collect();
+    let bins = bins(&profits);
+    let tallest = bins
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let width = WIDTH / BINS as f64;
+
+    let mut body = String::new();
+    for (index, (middle, count)) in bins.iter().enumerate() {
+        let height = *count as f64 / tallest as f64 * HEIGHT;
+        let x = MARGIN + index as f64 * width;
+        let color = if *middle < 0.0 { "#d0342c" } else { "#2a9d50" };
+        writeln!(
+            body,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>\n\
+             <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:.2}</text>",
+            x,
+            MARGIN + HEIGHT - height,
+            width - 2.0,
+            height,
+            color,
+            x + width / 2.0,
+            MARGIN + HEIGHT + 14.0,
+            middle
+        )
+        .unwrap();
+    }
+    body
+}
+
+// Closes with entries marked by triangles, green for longs and red for shorts,
+// and exits by circles.
+fn price(candles: &[Candle], positions: &[Position]) -> String {
+    let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+    if closes.is_empty() {
+        return String::new();
+    }
+    let scale = Scale::new(&closes, closes.len());
+    let x = |time| {
+        let index = candles.partition_point(|c| c.datetime < time);
+        scale.x(index.min(candles.len() - 1))
+    };
+
+    let mut body = scale.labels() + scale.polyline(&closes, "#555").as_str();
+    for position in positions {
+        let (entry_x, entry_y) = (x(position.time), scale.y(position.bid));
+        let color = if position.is_short() {
+            "#d0342c"
+        } else {
+            "#2a9d50"
+        };
+        writeln!(
+            body,
+            "<polygon points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\" fill=\"{}\"/>",
+            entry_x,
+            entry_y - 6.0,
+            entry_x - 5.0,
+            entry_y + 4.0,
+            entry_x + 5.0,
+            entry_y + 4.0,
+            color
+        )
+        .unwrap();
+        for close in &position.closes {
+            writeln!(
+                body,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"none\" stroke=\"#222\"/>",
+                x(close.time),
+                scale.y(close.ask)
+            )
+            .unwrap();
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bins, drawdowns, monthly_returns, Report};
+    use crate::{apis::candles::Candle, clock, trading::Position};
+
+    #[test]
+    fn monthly_returns_chain_from_the_previous_month() {
+        let candles: Vec<Candle> = [(9, 29), (9, 30), (10, 1), (10, 2)]
+            .iter()
+            .map(|(month, day)| {
+                let time = clock::datetime(2020, *month, *day, 10, 0, 0);
+                Candle::new(10.0, 10.0, 10.0, 10.0, 100, time)
+            })
+            .collect();
+        let months = monthly_returns(&candles, &[1000.0, 1100.0, 1000.0, 1210.0]);
+        assert_eq!(months.len(), 2);
+        assert_eq!((months[0].0, months[0].1), (2020, 9));
+        assert!((months[0].2 - 0.1).abs() < 1e-9);
+        assert!((months[1].2 - 0.1).abs() < 1e-9);
+
+        assert_eq!(
+            drawdowns(&[10.0, 12.0, 9.0, 13.0]),
+            vec![0.0, 0.0, -3.0, 0.0]
+        );
+        let counts: usize = bins(&[-5.0, -1.0, 0.0, 2.0, 7.0]).iter().map(|b| b.1).sum();
+        assert_eq!(counts, 5);
+    }
+
+    #[test]
+    fn report_is_a_single_page_of_inline_svg() {
+        let ticker = "ABC".to_string();
+        let candles: Vec<Candle> = (0..30)
+            .map(|minute| {
+                let close = 10.0 + (minute as f64 / 4.0).sin();
+                let time =
+                    clock::datetime(2020, 9, 29, 10, 0, 0) + clock::Duration::minutes(minute);
+                Candle::new(close, close, close + 0.1, close - 0.1, 100, time)
+            })
+            .collect();
+        let mut position = Position::open(&ticker, 10, candles[2].close, candles[2].datetime);
+        position.close(candles[8].close, candles[8].datetime);
+
+        let mut report = Report::default();
+        report.add(&ticker, &[position], &candles, 1000.0);
+        let html = report.html();
+        assert_eq!(html.matches("<svg").count(), 5);
+        assert!(html.contains("<polygon") && html.contains("<circle"));
+        assert!(!html.contains("<script"));
+    }
+}
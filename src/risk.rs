@@ -0,0 +1,306 @@
+use super::{clock, trading::Position};
+use serde::Deserialize;
+use std::{collections::VecDeque, fmt, fs};
+
+// Portfolio limits checked before an order reaches the Broker, loaded from a
+// TOML file, e.g.
+//
+//   max_daily_loss = 50.0
+//   max_open_positions = 3
+//   max_gross_exposure = 150.0
+//   max_net_exposure = 100.0
+//   max_symbol_exposure = 50.0
+//   max_orders_per_minute = 4
+//   price_band_percent = 5.0
+//
+// Exposures are percents of equity and max_daily_loss is in dollars. Orders
+// that close positions are never rejected.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RiskLimits {
+    pub max_daily_loss: Option<f64>,
+    pub max_open_positions: Option<usize>,
+    pub max_gross_exposure: Option<f64>,
+    pub max_net_exposure: Option<f64>,
+    pub max_symbol_exposure: Option<f64>,
+    pub max_orders_per_minute: Option<usize>,
+    // distance of an order's price from the last trade
+    pub price_band_percent: Option<f64>,
+}
+
+pub fn load(path: &str) -> Result<RiskLimits, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("couldn't open file {}: {}", path, err))?;
+    toml::from_str(&contents).map_err(|err| format!("invalid risk limits {}: {}", path, err))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RiskRejection {
+    Halted,
+    OpenPositions(usize),
+    GrossExposure(f64),
+    NetExposure(f64),
+    SymbolExposure(f64),
+    OrderRate(usize),
+    PriceBand { price: f64, last: f64 },
+}
+
+impl fmt::Display for RiskRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RiskRejection::Halted => write!(f, "trading halted after the daily loss limit"),
+            RiskRejection::OpenPositions(count) => write!(f, "{} positions already open", count),
+            RiskRejection::GrossExposure(percent) => {
+                write!(f, "gross exposure would be {:.2}% of equity", percent)
+            }
+            RiskRejection::NetExposure(percent) => {
+                write!(f, "net exposure would be {:.2}% of equity", percent)
+            }
+            RiskRejection::SymbolExposure(percent) => {
+                write!(f, "symbol exposure would be {:.2}% of equity", percent)
+            }
+            RiskRejection::OrderRate(count) => {
+                write!(f, "{} orders already sent in the last minute", count)
+            }
+            RiskRejection::PriceBand { price, last } => {
+                write!(f, "price ${} is outside the band around ${}", price, last)
+            }
+        }
+    }
+}
+
+pub struct Rejection {
+    pub ticker: String,
+    pub time: clock::LocalDateTime,
+    pub reason: RiskRejection,
+}
+
+impl fmt::Debug for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} - {}", self.ticker, self.time, self.reason)
+    }
+}
+
+// An order to open a position. Shares are negative for shorts.
+pub struct Order<'o> {
+    pub ticker: &'o str,
+    pub shares: i32,
+    pub price: f64,
+    pub time: clock::LocalDateTime,
+}
+
+// When the kill switch flattened the account, and the day's P/L that tripped it.
+pub struct Halt {
+    pub time: clock::LocalDateTime,
+    pub profit_and_loss: f64,
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} - daily loss limit hit (${:.2}), flattened and halted",
+            self.time, self.profit_and_loss
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct RiskManager {
+    pub limits: RiskLimits,
+    pub rejections: Vec<Rejection>,
+    pub halted: Option<Halt>,
+    order_times: VecDeque<clock::LocalDateTime>,
+}
+
+impl RiskManager {
+    pub fn new(limits: RiskLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted.is_some()
+    }
+
+    pub fn halt(&mut self, time: clock::LocalDateTime, profit_and_loss: f64) {
+        self.halted = Some(Halt {
+            time,
+            profit_and_loss,
+        });
+    }
+
+    // Records the rejection when the order breaks a limit.
+    pub fn approve(
+        &mut self,
+        order: &Order,
+        positions: &[Position],
+        equity: f64,
+        last_price: Option<f64>,
+    ) -> bool {
+        match self.check(order, positions, equity, last_price) {
+            Ok(()) => true,
+            Err(reason) => {
+                self.rejections.push(Rejection {
+                    ticker: order.ticker.to_string(),
+                    time: order.time,
+                    reason,
+                });
+                false
+            }
+        }
+    }
+
+    fn check(
+        &mut self,
+        order: &Order,
+        positions: &[Position],
+        equity: f64,
+        last_price: Option<f64>,
+    ) -> Result<(), RiskRejection> {
+        if self.is_halted() {
+            return Err(RiskRejection::Halted);
+        }
+
+        let open: Vec<&Position> = positions.iter().filter(|p| p.open).collect();
+        if let Some(max) = self.limits.max_open_positions {
+            if open.len() >= max {
+                return Err(RiskRejection::OpenPositions(open.len()));
+            }
+        }
+
+        let value = order.shares as f64 * order.price;
+        let percent_of_equity = |exposure: f64| exposure * 100.0 / equity;
+        let gross: f64 = open.iter().map(|p| (p.shares as f64 * p.bid).abs()).sum();
+        let gross = percent_of_equity(gross + value.abs());
+        if self
+            .limits
+            .max_gross_exposure
+            .is_some_and(|max| gross > max)
+        {
+            return Err(RiskRejection::GrossExposure(gross));
+        }
+        let net: f64 = open.iter().map(|p| p.shares as f64 * p.bid).sum();
+        let net = percent_of_equity((net + value).abs());
+        if self.limits.max_net_exposure.is_some_and(|max| net > max) {
+            return Err(RiskRejection::NetExposure(net));
+        }
+        let symbol: f64 = open
+            .iter()
+            .filter(|p| p.ticker == order.ticker)
+            .map(|p| (p.shares as f64 * p.bid).abs())
+            .sum();
+        let symbol = percent_of_equity(symbol + value.abs());
+        if self
+            .limits
+            .max_symbol_exposure
+            .is_some_and(|max| symbol > max)
+        {
+            return Err(RiskRejection::SymbolExposure(symbol));
+        }
+
+        if let Some(max) = self.limits.max_orders_per_minute {
+            let recent = self.orders_in_last_minute(order.time);
+            if recent >= max {
+                return Err(RiskRejection::OrderRate(recent));
+            }
+        }
+
+        if let (Some(band), Some(last)) = (self.limits.price_band_percent, last_price) {
+            if (order.price / last - 1.0).abs() * 100.0 > band {
+                return Err(RiskRejection::PriceBand {
+                    price: order.price,
+                    last,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Every order sent to the Broker counts towards the rate limit.
+    pub fn record_order(&mut self, time: clock::LocalDateTime) {
+        self.orders_in_last_minute(time);
+        self.order_times.push_back(time);
+    }
+
+    fn orders_in_last_minute(&mut self, time: clock::LocalDateTime) -> usize {
+        let start = time - clock::Duration::minutes(1);
+        while self.order_times.front().is_some_and(|t| *t <= start) {
+            self.order_times.pop_front();
+        }
+        self.order_times.len()
+    }
+
+    // True the first time the day's loss reaches max_daily_loss.
+    pub fn daily_loss_breached(&self, profit_and_loss: f64) -> bool {
+        !self.is_halted()
+            && self
+                .limits
+                .max_daily_loss
+                .is_some_and(|max| -profit_and_loss >= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Order, RiskLimits, RiskManager, RiskRejection};
+    use crate::{clock, trading::Position};
+
+    fn order(ticker: &str, shares: i32, price: f64, minute: i64) -> Order<'_> {
+        Order {
+            ticker,
+            shares,
+            price,
+            time: clock::datetime(2020, 9, 29, 10, 0, 0) + clock::Duration::minutes(minute),
+        }
+    }
+
+    fn reason(risk: &RiskManager) -> Option<&RiskRejection> {
+        risk.rejections.last().map(|rejection| &rejection.reason)
+    }
+
+    #[test]
+    fn exposure_limits_reject_orders_with_a_reason() {
+        let ticker = "ABC".to_string();
+        let mut risk = RiskManager::new(RiskLimits {
+            max_gross_exposure: Some(100.0),
+            max_symbol_exposure: Some(30.0),
+            ..RiskLimits::default()
+        });
+        let time = clock::datetime(2020, 9, 29, 9, 30, 0);
+        let positions = vec![Position::open(&ticker, 20, 10.0, time)];
+
+        assert!(!risk.approve(&order("ABC", 20, 10.0, 0), &positions, 1000.0, None));
+        assert_eq!(reason(&risk), Some(&RiskRejection::SymbolExposure(40.0)));
+        assert!(risk.approve(&order("XYZ", -25, 10.0, 0), &positions, 1000.0, None));
+        assert!(!risk.approve(&order("XYZ", 90, 10.0, 0), &positions, 1000.0, None));
+        assert_eq!(reason(&risk), Some(&RiskRejection::GrossExposure(110.0)));
+    }
+
+    #[test]
+    fn order_rate_and_price_band_are_enforced() {
+        let mut risk = RiskManager::new(RiskLimits {
+            max_orders_per_minute: Some(2),
+            price_band_percent: Some(5.0),
+            ..RiskLimits::default()
+        });
+        risk.record_order(order("ABC", 1, 10.0, 0).time);
+        risk.record_order(order("ABC", 1, 10.0, 0).time);
+        assert!(!risk.approve(&order("ABC", 1, 10.0, 0), &[], 1000.0, Some(10.0)));
+        assert_eq!(reason(&risk), Some(&RiskRejection::OrderRate(2)));
+
+        assert!(!risk.approve(&order("ABC", 1, 11.0, 1), &[], 1000.0, Some(10.0)));
+        assert!(risk.approve(&order("ABC", 1, 10.4, 1), &[], 1000.0, Some(10.0)));
+    }
+
+    #[test]
+    fn risk_limits_are_loaded_from_toml() {
+        let limits: RiskLimits =
+            toml::from_str("max_daily_loss = 50.0\nmax_open_positions = 2").unwrap();
+        assert_eq!(limits.max_daily_loss, Some(50.0));
+        assert_eq!(limits.max_open_positions, Some(2));
+        assert!(toml::from_str::<RiskLimits>("max_loss = 50.0").is_err());
+    }
+}
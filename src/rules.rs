@@ -0,0 +1,439 @@
+use super::{apis::candles::Candle, studies};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+// A value calculated from the candle stream. Every indicator must be updated
+// exactly once per candle and returns None until it has warmed up.
+pub enum Indicator {
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+    Constant(f64),
+    Sma(Box<Indicator>, studies::SMA),
+    Atr(studies::ATR),
+    // change per bar of the inner value, averaged over `bars`
+    Slope(Box<Indicator>, usize, VecDeque<f64>),
+    // percent distance of the first value from the second
+    PercentDistance(Box<Indicator>, Box<Indicator>),
+    // a level of the session's developing value area, or the prior session's
+    ValueArea(studies::VolumeProfile, ValueAreaLevel, bool),
+    PriorDay(studies::PriorDay, DayLevel),
+    Pivot(studies::PivotPoints, PivotLevel),
+    OpeningRange(studies::OpeningRange, RangeLevel),
+    RelativeVolume(studies::RelativeVolume),
+    IntradayVolume(studies::IntradayVolume),
+    GapPercent(studies::GapPercent),
+}
+
+// Levels of the session studies, named as they are in strategy definitions.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueAreaLevel {
+    Poc,
+    High,
+    Low,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DayLevel {
+    High,
+    Low,
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PivotLevel {
+    Pivot,
+    R1,
+    R2,
+    R3,
+    S1,
+    S2,
+    S3,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RangeLevel {
+    High,
+    Low,
+    Midpoint,
+}
+
+impl Indicator {
+    pub fn update(&mut self, candle: &Candle) -> Option<f64> {
+        match self {
+            Indicator::Open => Some(candle.open),
+            Indicator::High => Some(candle.high),
+            Indicator::Low => Some(candle.low),
+            Indicator::Close => Some(candle.close),
+            Indicator::Volume => Some(candle.volume as f64),
+            Indicator::Constant(value) => Some(*value),
+            Indicator::Sma(source, sma) => {
+                if let Some(value) = source.update(candle) {
+                    sma.add(value);
+                }
+                sma.value
+            }
+            Indicator::Atr(atr) => {
+                atr.add(candle);
+                atr.value
+            }
+            Indicator::Slope(source, bars, values) => {
+                let value = source.update(candle)?;
+                values.push_back(value);
+                if values.len() > *bars + 1 {
+                    values.pop_front();
+                }
+                if values.len() <= *bars {
+                    return None;
+                }
+                Some((values[*bars] - values[0]) / *bars as f64)
+            }
+            Indicator::PercentDistance(a, b) => {
+                let (a, b) = (a.update(candle), b.update(candle));
+                let (a, b) = (a?, b?);
+                if b == 0.0 {
+                    return None;
+                }
+                Some((a - b) / b * 100.0)
+            }
+            Indicator::ValueArea(profile, level, prior) => {
+                profile.add(candle);
+                let area = if *prior { profile.prior } else { profile.value }?;
+                Some(match level {
+                    ValueAreaLevel::Poc => area.poc,
+                    ValueAreaLevel::High => area.high,
+                    ValueAreaLevel::Low => area.low,
+                })
+            }
+            Indicator::PriorDay(prior_day, level) => {
+                prior_day.add(candle);
+                let day = prior_day.value?;
+                Some(match level {
+                    DayLevel::High => day.high,
+                    DayLevel::Low => day.low,
+                    DayLevel::Close => day.close,
+                })
+            }
+            Indicator::Pivot(pivot_points, level) => {
+                pivot_points.add(candle);
+                let pivots = pivot_points.value?;
+                Some(match level {
+                    PivotLevel::Pivot => pivots.pivot,
+                    PivotLevel::R1 => pivots.r1,
+                    PivotLevel::R2 => pivots.r2,
+                    PivotLevel::R3 => pivots.r3,
+                    PivotLevel::S1 => pivots.s1,
+                    PivotLevel::S2 => pivots.s2,
+                    PivotLevel::S3 => pivots.s3,
+                })
+            }
+            Indicator::OpeningRange(opening_range, level) => {
+                opening_range.add(candle);
+                let range = opening_range.value?;
+                Some(match level {
+                    RangeLevel::High => range.high,
+                    RangeLevel::Low => range.low,
+                    RangeLevel::Midpoint => range.midpoint(),
+                })
+            }
+            Indicator::RelativeVolume(relative_volume) => {
+                relative_volume.add(candle);
+                relative_volume.value
+            }
+            Indicator::IntradayVolume(intraday_volume) => {
+                intraday_volume.add(candle);
+                intraday_volume.value
+            }
+            Indicator::GapPercent(gap) => {
+                gap.add(candle);
+                gap.value
+            }
+        }
+    }
+}
+
+// A boolean rule over indicators, evaluated once per candle. Returns None
+// while any indicator it depends on is still warming up.
+pub enum Condition {
+    Above(Indicator, Indicator),
+    Below(Indicator, Indicator),
+    CrossesAbove(Indicator, Indicator, Option<(f64, f64)>),
+    CrossesBelow(Indicator, Indicator, Option<(f64, f64)>),
+    // first value above the second for at least `bars` consecutive candles
+    AboveFor(Indicator, Indicator, usize, usize),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn update(&mut self, candle: &Candle) -> Option<bool> {
+        match self {
+            Condition::Above(a, b) => {
+                let (a, b) = (a.update(candle), b.update(candle));
+                Some(a? > b?)
+            }
+            Condition::Below(a, b) => {
+                let (a, b) = (a.update(candle), b.update(candle));
+                Some(a? < b?)
+            }
+            Condition::CrossesAbove(a, b, previous) => {
+                let current = (a.update(candle), b.update(candle));
+                let last = previous.take();
+                let (a, b) = (current.0?, current.1?);
+                *previous = Some((a, b));
+                let (last_a, last_b) = last?;
+                Some(last_a <= last_b && a > b)
+            }
+            Condition::CrossesBelow(a, b, previous) => {
+                let current = (a.update(candle), b.update(candle));
+                let last = previous.take();
+                let (a, b) = (current.0?, current.1?);
+                *previous = Some((a, b));
+                let (last_a, last_b) = last?;
+                Some(last_a >= last_b && a < b)
+            }
+            Condition::AboveFor(a, b, bars, count) => {
+                let (a, b) = (a.update(candle), b.update(candle));
+                match (a, b) {
+                    (Some(a), Some(b)) if a > b => *count += 1,
+                    (Some(_), Some(_)) => *count = 0,
+                    _ => {
+                        *count = 0;
+                        return None;
+                    }
+                }
+                Some(*count >= *bars)
+            }
+            Condition::And(conditions) => {
+                // every condition is updated so none of them miss a candle
+                let results: Vec<Option<bool>> =
+                    conditions.iter_mut().map(|c| c.update(candle)).collect();
+                let mut all = true;
+                for result in results {
+                    all &= result?;
+                }
+                Some(all)
+            }
+            Condition::Or(conditions) => {
+                let results: Vec<Option<bool>> =
+                    conditions.iter_mut().map(|c| c.update(candle)).collect();
+                let mut any = false;
+                for result in results {
+                    any |= result?;
+                }
+                Some(any)
+            }
+            Condition::Not(condition) => condition.update(candle).map(|result| !result),
+        }
+    }
+
+    // Convenience for strategies that treat a warming up rule as not met.
+    pub fn is_met(&mut self, candle: &Candle) -> bool {
+        self.update(candle).unwrap_or(false)
+    }
+}
+
+pub fn open() -> Indicator {
+    Indicator::Open
+}
+
+pub fn high() -> Indicator {
+    Indicator::High
+}
+
+pub fn low() -> Indicator {
+    Indicator::Low
+}
+
+pub fn close() -> Indicator {
+    Indicator::Close
+}
+
+pub fn volume() -> Indicator {
+    Indicator::Volume
+}
+
+pub fn constant(value: f64) -> Indicator {
+    Indicator::Constant(value)
+}
+
+pub fn sma(bars: usize) -> Indicator {
+    sma_of(close(), bars)
+}
+
+pub fn sma_of(source: Indicator, bars: usize) -> Indicator {
+    Indicator::Sma(Box::new(source), studies::SMA::new(bars))
+}
+
+pub fn atr(bars: usize) -> Indicator {
+    Indicator::Atr(studies::ATR::new(bars))
+}
+
+pub fn slope(source: Indicator, bars: usize) -> Indicator {
+    Indicator::Slope(Box::new(source), bars, VecDeque::new())
+}
+
+pub fn percent_distance(a: Indicator, b: Indicator) -> Indicator {
+    Indicator::PercentDistance(Box::new(a), Box::new(b))
+}
+
+pub fn value_area(tick: f64, level: ValueAreaLevel, prior: bool) -> Indicator {
+    Indicator::ValueArea(studies::VolumeProfile::new(tick), level, prior)
+}
+
+pub fn prior_day(level: DayLevel) -> Indicator {
+    Indicator::PriorDay(studies::PriorDay::new(), level)
+}
+
+pub fn pivot(level: PivotLevel) -> Indicator {
+    Indicator::Pivot(studies::PivotPoints::new(), level)
+}
+
+pub fn opening_range(minutes: i64, level: RangeLevel) -> Indicator {
+    Indicator::OpeningRange(studies::OpeningRange::new(minutes), level)
+}
+
+pub fn relative_volume(sessions: usize) -> Indicator {
+    Indicator::RelativeVolume(studies::RelativeVolume::new(sessions))
+}
+
+pub fn intraday_volume(sessions: usize) -> Indicator {
+    Indicator::IntradayVolume(studies::IntradayVolume::new(sessions))
+}
+
+pub fn gap_percent() -> Indicator {
+    Indicator::GapPercent(studies::GapPercent::new())
+}
+
+pub fn above(a: Indicator, b: Indicator) -> Condition {
+    Condition::Above(a, b)
+}
+
+pub fn below(a: Indicator, b: Indicator) -> Condition {
+    Condition::Below(a, b)
+}
+
+pub fn crosses_above(a: Indicator, b: Indicator) -> Condition {
+    Condition::CrossesAbove(a, b, None)
+}
+
+pub fn crosses_below(a: Indicator, b: Indicator) -> Condition {
+    Condition::CrossesBelow(a, b, None)
+}
+
+pub fn above_for(a: Indicator, b: Indicator, bars: usize) -> Condition {
+    Condition::AboveFor(a, b, bars, 0)
+}
+
+pub fn and(conditions: Vec<Condition>) -> Condition {
+    Condition::And(conditions)
+}
+
+pub fn or(conditions: Vec<Condition>) -> Condition {
+    Condition::Or(conditions)
+}
+
+pub fn not(condition: Condition) -> Condition {
+    Condition::Not(Box::new(condition))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::candles::candle;
+
+    fn candles(closes: &[f64]) -> Vec<Candle> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, close)| candle(*close, *close, *close, *close, 100, i as i64))
+            .collect()
+    }
+
+    fn evaluate(condition: &mut Condition, closes: &[f64]) -> Vec<Option<bool>> {
+        candles(closes)
+            .iter()
+            .map(|candle| condition.update(candle))
+            .collect()
+    }
+
+    #[test]
+    fn crossover_is_none_while_warming_up() {
+        let mut rule = crosses_above(close(), sma(2));
+        assert_eq!(
+            evaluate(&mut rule, &[5.0, 4.0, 3.0, 6.0, 7.0]),
+            vec![None, None, Some(false), Some(true), Some(false)]
+        );
+    }
+
+    #[test]
+    fn crossunder_fires_once_on_the_cross() {
+        let mut rule = crosses_below(close(), constant(5.0));
+        assert_eq!(
+            evaluate(&mut rule, &[6.0, 4.0, 3.0, 6.0]),
+            vec![None, Some(true), Some(false), Some(false)]
+        );
+    }
+
+    #[test]
+    fn above_for_counts_consecutive_bars() {
+        let mut rule = above_for(close(), constant(5.0), 2);
+        assert_eq!(
+            evaluate(&mut rule, &[6.0, 6.0, 4.0, 6.0, 6.0]),
+            vec![
+                Some(false),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn slope_is_average_change_per_bar() {
+        let mut slope = slope(close(), 2);
+        let values: Vec<Option<f64>> = candles(&[1.0, 2.0, 5.0])
+            .iter()
+            .map(|candle| slope.update(candle))
+            .collect();
+        assert_eq!(values, vec![None, None, Some(2.0)]);
+    }
+
+    #[test]
+    fn percent_distance_is_relative_to_second_value() {
+        let mut rule = above(percent_distance(close(), constant(10.0)), constant(5.0));
+        assert_eq!(
+            evaluate(&mut rule, &[10.4, 11.0]),
+            vec![Some(false), Some(true)]
+        );
+    }
+
+    #[test]
+    fn and_waits_for_every_condition_to_warm_up() {
+        let mut rule = and(vec![above(close(), sma(3)), above(close(), constant(1.0))]);
+        assert_eq!(
+            evaluate(&mut rule, &[2.0, 3.0, 4.0, 1.5]),
+            vec![None, None, Some(true), Some(false)]
+        );
+    }
+
+    #[test]
+    fn or_and_not_combine_conditions() {
+        let mut rule = or(vec![
+            above(close(), constant(10.0)),
+            not(above(close(), constant(2.0))),
+        ]);
+        assert_eq!(
+            evaluate(&mut rule, &[11.0, 5.0, 1.0]),
+            vec![Some(true), Some(false), Some(true)]
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use super::{
+    apis::candles::Candle,
+    engine::Signal,
+    studies,
+    trading::{Account, Broker, PriceData},
+};
+use rhai::{
+    module_resolvers::DummyModuleResolver, CallFnOptions, Dynamic, Engine, Map, Scope, AST,
+};
+use std::{cell::RefCell, collections::HashMap, fs, rc::Rc};
+
+// Limits that keep a misbehaving script from hanging or exhausting a backtest.
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 1_000;
+const MAX_COLLECTION_SIZE: usize = 1_000;
+// candles kept to backfill studies a script first asks for mid-run
+const MAX_STUDY_CANDLES: usize = 500;
+
+#[derive(Debug, PartialEq)]
+pub enum Intent {
+    Buy,
+    Sell,
+    Hold,
+}
+
+// Studies are calculated by the host and looked up by scripts, e.g. `sma(9)`.
+// A study first requested mid-run is backfilled from the last MAX_STUDY_CANDLES
+// candles.
+#[derive(Default)]
+struct ScriptStudies {
+    candles: Vec<Candle>,
+    smas: HashMap<usize, studies::SMA>,
+    atrs: HashMap<usize, studies::ATR>,
+}
+
+impl ScriptStudies {
+    fn add(&mut self, candle: &Candle) {
+        for sma in self.smas.values_mut() {
+            sma.add(candle.close);
+        }
+        for atr in self.atrs.values_mut() {
+            atr.add(candle);
+        }
+        self.candles.push(candle.clone());
+        if self.candles.len() > MAX_STUDY_CANDLES {
+            self.candles.remove(0);
+        }
+    }
+
+    fn sma(&mut self, bars: usize) -> Option<f64> {
+        let candles = &self.candles;
+        self.smas
+            .entry(bars)
+            .or_insert_with(|| {
+                let mut sma = studies::SMA::new(bars);
+                for candle in candles {
+                    sma.add(candle.close);
+                }
+                sma
+            })
+            .value
+    }
+
+    fn atr(&mut self, bars: usize) -> Option<f64> {
+        let candles = &self.candles;
+        self.atrs
+            .entry(bars)
+            .or_insert_with(|| {
+                let mut atr = studies::ATR::new(bars);
+                for candle in candles {
+                    atr.add(candle);
+                }
+                atr
+            })
+            .value
+    }
+}
+
+fn to_dynamic(value: Option<f64>) -> Dynamic {
+    match value {
+        Some(value) => Dynamic::from_float(value),
+        None => Dynamic::UNIT,
+    }
+}
+
+// A strategy written in Rhai. The script defines `on_candle(candle, account)`
+// and returns "buy", "sell" or "hold". `this` is a map the script can use to
+// keep state between candles. For example:
+//
+//   fn on_candle(candle, account) {
+//       let sma9 = sma(9);
+//       if sma9 == () { return "hold"; }
+//       if !account.position_open && candle.close > sma9 { return "buy"; }
+//       if account.position_open && candle.close < sma9 { return "sell"; }
+//       "hold"
+//   }
+pub struct ScriptStrategy<'a> {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    studies: Rc<RefCell<ScriptStudies>>,
+    ticker: &'a String,
+}
+
+impl<'a> ScriptStrategy<'a> {
+    pub fn load(path: &str, ticker: &'a String, candles: &[Candle]) -> Result<Self, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("couldn't open file {}: {}", path, err))?;
+        Self::new(&source, ticker, candles)
+    }
+
+    pub fn new(source: &str, ticker: &'a String, candles: &[Candle]) -> Result<Self, String> {
+        let studies = Rc::new(RefCell::new(ScriptStudies::default()));
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval");
+
+        let sma_studies = Rc::clone(&studies);
+        engine.register_fn("sma", move |bars: i64| {
+            to_dynamic(sma_studies.borrow_mut().sma(bars.max(1) as usize))
+        });
+        let atr_studies = Rc::clone(&studies);
+        engine.register_fn("atr", move |bars: i64| {
+            to_dynamic(atr_studies.borrow_mut().atr(bars.max(1) as usize))
+        });
+
+        let ast = engine
+            .compile(source)
+            .map_err(|err| format!("script error: {}", err))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "on_candle" && f.params.len() == 2)
+        {
+            return Err("script must define on_candle(candle, account)".to_string());
+        }
+
+        // top level statements run once, before the first candle
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| format!("script error: {}", err))?;
+
+        // init studies
+        for candle in candles {
+            studies.borrow_mut().add(candle);
+        }
+
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            state: Dynamic::from_map(Map::new()),
+            studies,
+            ticker,
+        })
+    }
+
+    pub fn on_candle(&mut self, candle: &Candle, account: Map) -> Result<Intent, String> {
+        self.studies.borrow_mut().add(candle);
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let result = self
+            .engine
+            .call_fn_with_options::<Dynamic>(
+                options,
+                &mut self.scope,
+                &self.ast,
+                "on_candle",
+                (candle_map(candle), account),
+            )
+            .map_err(|err| format!("script error at {}: {}", candle.datetime, err))?;
+
+        match result.into_string().as_deref() {
+            Ok("buy") => Ok(Intent::Buy),
+            Ok("sell") => Ok(Intent::Sell),
+            Ok("hold") => Ok(Intent::Hold),
+            _ => Err(format!(
+                "script error at {}: on_candle must return \"buy\", \"sell\" or \"hold\"",
+                candle.datetime
+            )),
+        }
+    }
+
+    // Stops at the first script error, since the rest of the run would trade
+    // without the script's decisions.
+    pub fn execute<B: Broker>(
+        &mut self,
+        price_data: &mut PriceData,
+        account: &mut Account<'a, B>,
+    ) -> Result<(), String> {
+        while let Some(candle) = price_data.next_candle() {
+            account.add_candle(self.ticker, candle);
+            let intent = self.on_candle(candle, account_map(account, candle))?;
+
+            if intent == Intent::Buy && !account.is_position_open() {
+                let shares = account.position_size(self.ticker, candle.close, candle.datetime);
+                account.send_order(
+                    self.ticker,
+                    Signal::Buy,
+                    shares,
+                    candle.close,
+                    candle.datetime,
+                );
+            } else if intent == Intent::Sell && account.is_position_open() {
+                account.send_order(self.ticker, Signal::Close, 0, candle.close, candle.datetime);
+            }
+
+            account.close_position_for_day(self.ticker, candle);
+        }
+        Ok(())
+    }
+}
+
+fn candle_map(candle: &Candle) -> Map {
+    let mut map = Map::new();
+    map.insert("open".into(), Dynamic::from_float(candle.open));
+    map.insert("high".into(), Dynamic::from_float(candle.high));
+    map.insert("low".into(), Dynamic::from_float(candle.low));
+    map.insert("close".into(), Dynamic::from_float(candle.close));
+    map.insert("volume".into(), Dynamic::from_int(candle.volume));
+    map.insert(
+        "time".into(),
+        Dynamic::from(candle.datetime.format("%Y-%m-%d %H:%M:%S").to_string()),
+    );
+    map
+}
+
+fn account_map<B: Broker>(account: &mut Account<B>, candle: &Candle) -> Map {
+    let mut map = Map::new();
+    let cash = account.total_cash(candle.datetime);
+    map.insert("cash".into(), Dynamic::from_float(cash));
+    map.insert(
+        "position_open".into(),
+        Dynamic::from_bool(account.is_position_open()),
+    );
+
+    let (shares, entry_price) = match account.current_position() {
+        Some(position) if position.open => (position.shares as i64, position.bid),
+        _ => (0, 0.0),
+    };
+    map.insert("shares".into(), Dynamic::from_int(shares));
+    map.insert("entry_price".into(), Dynamic::from_float(entry_price));
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Intent, ScriptStrategy, ScriptStudies, MAX_STUDY_CANDLES};
+    use crate::{
+        apis::candles::candle,
+        backtest::BacktestBroker,
+        trading::{Account, PriceData},
+    };
+    use rhai::{Dynamic, Map};
+
+    fn account(position_open: bool) -> Map {
+        let mut map = Map::new();
+        map.insert("position_open".into(), Dynamic::from_bool(position_open));
+        map
+    }
+
+    #[test]
+    fn script_uses_host_studies_and_returns_intents() {
+        let ticker = "ABC".to_string();
+        let source = r#"
+            fn on_candle(candle, account) {
+                let sma2 = sma(2);
+                if sma2 == () { return "hold"; }
+                if !account.position_open && candle.close > sma2 { return "buy"; }
+                if account.position_open && candle.close < sma2 { return "sell"; }
+                "hold"
+            }
+        "#;
+        let history = [candle(10.0, 10.0, 10.0, 10.0, 100, 0)];
+        let mut strategy = ScriptStrategy::new(source, &ticker, &history).unwrap();
+
+        assert_eq!(
+            strategy.on_candle(&candle(12.0, 12.0, 12.0, 12.0, 100, 1), account(false)),
+            Ok(Intent::Buy)
+        );
+        assert_eq!(
+            strategy.on_candle(&candle(13.0, 13.0, 13.0, 13.0, 100, 2), account(true)),
+            Ok(Intent::Hold)
+        );
+        assert_eq!(
+            strategy.on_candle(&candle(11.0, 11.0, 11.0, 11.0, 100, 3), account(true)),
+            Ok(Intent::Sell)
+        );
+    }
+
+    #[test]
+    fn script_keeps_state_on_this() {
+        let ticker = "ABC".to_string();
+        let source = r#"
+            fn on_candle(candle, account) {
+                if this.count == () { this.count = 0; }
+                this.count += 1;
+                if this.count >= 2 { "buy" } else { "hold" }
+            }
+        "#;
+        let mut strategy = ScriptStrategy::new(source, &ticker, &[]).unwrap();
+        assert_eq!(
+            strategy.on_candle(&candle(1.0, 1.0, 1.0, 1.0, 100, 0), account(false)),
+            Ok(Intent::Hold)
+        );
+        assert_eq!(
+            strategy.on_candle(&candle(1.0, 1.0, 1.0, 1.0, 100, 1), account(false)),
+            Ok(Intent::Buy)
+        );
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let ticker = "ABC".to_string();
+        let source = r#"
+            fn on_candle(candle, account) {
+                loop { }
+            }
+        "#;
+        let mut strategy = ScriptStrategy::new(source, &ticker, &[]).unwrap();
+        assert!(strategy
+            .on_candle(&candle(1.0, 1.0, 1.0, 1.0, 100, 0), account(false))
+            .is_err());
+    }
+
+    #[test]
+    fn scripts_must_define_on_candle_and_return_an_intent() {
+        let ticker = "ABC".to_string();
+        assert!(ScriptStrategy::new("fn other() { 1 }", &ticker, &[]).is_err());
+        assert!(ScriptStrategy::new("eval(\"1\")", &ticker, &[]).is_err());
+
+        let source = "fn on_candle(candle, account) { 42 }";
+        let mut strategy = ScriptStrategy::new(source, &ticker, &[]).unwrap();
+        assert!(strategy
+            .on_candle(&candle(1.0, 1.0, 1.0, 1.0, 100, 0), account(false))
+            .is_err());
+    }
+
+    #[test]
+    fn a_script_error_stops_the_run() {
+        let ticker = "ABC".to_string();
+        let source = r#"
+            fn on_candle(candle, account) {
+                if candle.close > 1.0 { throw "too high"; }
+                "buy"
+            }
+        "#;
+        let mut strategy = ScriptStrategy::new(source, &ticker, &[]).unwrap();
+        let mut price_data = PriceData::with_candles(vec![
+            candle(1.0, 1.0, 1.0, 1.0, 100, 0),
+            candle(2.0, 2.0, 2.0, 2.0, 100, 1),
+            candle(1.0, 1.0, 1.0, 1.0, 100, 2),
+        ]);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+
+        let result = strategy.execute(&mut price_data, &mut account);
+        assert!(result.unwrap_err().contains("too high"));
+        assert_eq!(account.positions.len(), 1);
+        assert_eq!(
+            price_data.next_candle().unwrap().datetime,
+            candle(1.0, 1.0, 1.0, 1.0, 100, 2).datetime
+        );
+    }
+
+    #[test]
+    fn study_candles_are_bounded() {
+        let mut studies = ScriptStudies::default();
+        for minute in 0..MAX_STUDY_CANDLES as i64 + 10 {
+            studies.add(&candle(
+                minute as f64,
+                minute as f64,
+                minute as f64,
+                minute as f64,
+                100,
+                minute,
+            ));
+        }
+        assert_eq!(studies.candles.len(), MAX_STUDY_CANDLES);
+        assert_eq!(studies.candles[0].close, 10.0);
+        assert!(studies.sma(MAX_STUDY_CANDLES).is_some());
+    }
+}
@@ -1,11 +1,23 @@
 use super::{
     apis::alpha_vantage,
-    clock, config, strategies,
+    clock, config,
+    engine::{self, EngineConfig},
+    pdt::{self, DayTrades},
+    strategies,
     trading::{Account, Broker, PriceData},
 };
+use std::collections::HashMap;
 
 const COMMISSION: f64 = 0.01; // TD Ameritrade's trade commission
 
+// Reg-T margin requirements
+const INITIAL_MARGIN: f64 = 0.5;
+const LONG_MAINTENANCE: f64 = 0.25;
+const SHORT_MAINTENANCE: f64 = 0.3;
+// day trading buying power for accounts over the pattern day trader minimum
+const INTRADAY_LEVERAGE: f64 = 4.0;
+const MARGIN_RATE: f64 = 0.08; // yearly, charged on debit balances
+
 pub struct SimBroker {
     capital: f64,
     unsettled_cash: f64,
@@ -47,7 +59,7 @@ impl Broker for SimBroker {
 
     fn buy_order(
         &mut self,
-        _ticker: &String,
+        _ticker: &str,
         shares: i32,
         price: f64,
         _time: clock::LocalDateTime,
@@ -65,13 +77,7 @@ impl Broker for SimBroker {
         Some(self.capital)
     }
 
-    fn sell_order(
-        &mut self,
-        _ticker: &String,
-        shares: i32,
-        price: f64,
-        time: clock::LocalDateTime,
-    ) {
+    fn sell_order(&mut self, _ticker: &str, shares: i32, price: f64, time: clock::LocalDateTime) {
         self.unsettled_cash = (price * shares as f64) - COMMISSION;
         let mut settle_date = time.date() + clock::days(2);
         while clock::day_of_week(settle_date) > 5 {
@@ -81,20 +87,204 @@ impl Broker for SimBroker {
     }
 }
 
-pub fn run_simulation(tickers: &[String], env: &config::Env) {
-    println!("Running simulation for {}", tickers[0]);
-    let broker = SimBroker::new();
-    let mut account: Account<SimBroker> = Account::new(broker);
-    let mut price_data = PriceData::new(alpha_vantage::client(&env));
+// A margin account. Cash goes negative when positions are bought on margin and
+// is charged interest daily. Positions are valued at the latest mark, and the
+// account is liquidated when equity falls below the maintenance requirement.
+pub struct MarginBroker {
+    cash: f64,
+    // shares, negative when short, and the latest price
+    holdings: HashMap<String, (i32, f64)>,
+    pub interest_paid: f64,
+    interest_date: Option<clock::LocalDate>,
+}
+
+impl MarginBroker {
+    pub fn new(capital: f64) -> Self {
+        Self {
+            cash: capital,
+            holdings: HashMap::new(),
+            interest_paid: 0.0,
+            interest_date: None,
+        }
+    }
+
+    fn market_value(&self) -> f64 {
+        self.holdings
+            .values()
+            .map(|(shares, price)| *shares as f64 * price)
+            .sum()
+    }
+
+    fn gross_value(&self) -> f64 {
+        self.holdings
+            .values()
+            .map(|(shares, price)| (*shares as f64 * price).abs())
+            .sum()
+    }
+
+    pub fn account_equity(&self) -> f64 {
+        self.cash + self.market_value()
+    }
+
+    fn maintenance_requirement(&self) -> f64 {
+        self.holdings
+            .values()
+            .map(|(shares, price)| {
+                let value = *shares as f64 * price;
+                if value < 0.0 {
+                    -value * SHORT_MAINTENANCE
+                } else {
+                    value * LONG_MAINTENANCE
+                }
+            })
+            .sum()
+    }
+
+    fn accrue_interest(&mut self, time: clock::LocalDateTime) {
+        let date = time.date();
+        if let Some(last) = self.interest_date {
+            let days = (date - last).num_days();
+            if days > 0 && self.cash < 0.0 {
+                let interest = -self.cash * MARGIN_RATE / 360.0 * days as f64;
+                self.cash -= interest;
+                self.interest_paid += interest;
+            }
+        }
+        if self.interest_date.is_none_or(|last| date > last) {
+            self.interest_date = Some(date);
+        }
+    }
+
+    fn fill(&mut self, ticker: &str, shares: i32, price: f64, time: clock::LocalDateTime) {
+        self.accrue_interest(time);
+        self.cash -= shares as f64 * price;
+        let holding = self
+            .holdings
+            .entry(ticker.to_string())
+            .or_insert((0, price));
+        holding.0 += shares;
+        holding.1 = price;
+        if holding.0 == 0 {
+            self.holdings.remove(ticker);
+        }
+    }
+}
+
+impl Broker for MarginBroker {
+    // Buying power: 4x equity during the session for accounts over $25,000,
+    // otherwise what the 50% initial requirement allows.
+    fn capital(&mut self, time: clock::LocalDateTime) -> f64 {
+        self.accrue_interest(time);
+        let equity = self.account_equity();
+        let leverage = if equity >= pdt::MIN_EQUITY && clock::is_regular_hours(time) {
+            INTRADAY_LEVERAGE
+        } else {
+            1.0 / INITIAL_MARGIN
+        };
+        (equity * leverage - self.gross_value()).max(0.0)
+    }
+
+    fn unsettled_cash(&self) -> f64 {
+        0.0
+    }
+
+    fn is_market_open(&self, datetime: clock::LocalDateTime) -> bool {
+        let open = clock::Time::from_hms(9, 30, 0);
+        let close = clock::Time::from_hms(16, 0, 0);
+        let time = datetime.time();
+        let day_of_week: i32 = datetime.date().format("%u").to_string().parse().unwrap();
+        time >= open && time < close && day_of_week < 6
+    }
+
+    fn equity(&mut self, time: clock::LocalDateTime, _open_value: f64) -> f64 {
+        self.accrue_interest(time);
+        self.account_equity()
+    }
+
+    fn buy_order(
+        &mut self,
+        ticker: &str,
+        shares: i32,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) -> Option<f64> {
+        if price * shares as f64 > self.capital(time) {
+            return None;
+        }
+        self.fill(ticker, shares, price, time);
+        Some(self.cash)
+    }
+
+    fn sell_order(&mut self, ticker: &str, shares: i32, price: f64, time: clock::LocalDateTime) {
+        self.fill(ticker, -shares, price, time);
+        self.cash -= COMMISSION;
+    }
+
+    fn short_order(
+        &mut self,
+        ticker: &str,
+        shares: i32,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) -> Option<f64> {
+        if price * shares as f64 > self.capital(time) {
+            return None;
+        }
+        self.fill(ticker, -shares, price, time);
+        Some(self.cash)
+    }
+
+    fn cover_order(&mut self, ticker: &str, shares: i32, price: f64, time: clock::LocalDateTime) {
+        self.fill(ticker, shares, price, time);
+        self.cash -= COMMISSION;
+    }
 
-    if let Some(candles) = price_data.history(&tickers[0], 9, "1:minute") {
-        let mut strategy = strategies::SmaCrossover::new(&tickers[0], candles);
-        strategy.execute(&mut price_data, &mut account);
+    fn mark(&mut self, ticker: &str, price: f64, time: clock::LocalDateTime) {
+        self.accrue_interest(time);
+        if let Some(holding) = self.holdings.get_mut(ticker) {
+            holding.1 = price;
+        }
+    }
+
+    fn margin_call(&self) -> bool {
+        !self.holdings.is_empty() && self.account_equity() < self.maintenance_requirement()
+    }
+}
+
+pub fn run_simulation(tickers: &[String], env: &config::Env, margin: bool) {
+    println!("Running simulation for {}", tickers[0]);
+    if margin {
+        if let Some(account) = simulate(&tickers[0], MarginBroker::new(1000.0), env) {
+            println!("Margin interest: ${:.4}", account.broker.interest_paid);
+            log_results(account);
+        }
+    } else if let Some(account) = simulate(&tickers[0], SimBroker::new(), env) {
         log_results(account);
     }
 }
 
-fn log_results(mut account: Account<SimBroker>) {
+fn simulate<'a, B: Broker>(
+    ticker: &'a String,
+    broker: B,
+    env: &config::Env,
+) -> Option<Account<'a, B>> {
+    let mut account = Account::new(broker);
+    account.day_trades = DayTrades::enforced();
+    let mut price_data = PriceData::new(alpha_vantage::client(env));
+
+    let candles = price_data.history(ticker, 9, "1:minute")?;
+    let mut strategy = strategies::SmaCrossover::new(candles);
+    engine::run_strategy(
+        ticker,
+        &mut strategy,
+        &mut price_data,
+        &mut account,
+        EngineConfig::default(),
+    );
+    Some(account)
+}
+
+pub fn log_results<B: Broker>(mut account: Account<B>) {
     let mut winning_trades = Vec::new();
     let mut losing_trades = Vec::new();
     for position in &account.positions {
@@ -123,15 +313,36 @@ fn log_results(mut account: Account<SimBroker>) {
         wins_sum + losses_sum,
     );
 
+    for flag in &account.day_trades.flags {
+        println!("PDT {}", flag);
+    }
+    for time in &account.margin_calls {
+        println!("Margin call {} - liquidated", time);
+    }
+    for gap in &account.suspect_gaps {
+        println!("Suspect gap {}", gap);
+    }
+    if let Some(halt) = &account.risk.halted {
+        println!("Halted {}", halt);
+    }
+    println!("Day trades: {}", account.day_trades.total());
+
     let time = clock::milliseconds_to_date(0);
-    println!("Ending Capital: ${:.4}", account.total_cash(time));
+    println!("Ending Capital: ${:.4}", account.equity(time));
 }
 
 #[cfg(test)]
 mod tests {
-    use super::SimBroker;
+    use super::{MarginBroker, SimBroker};
     use crate::{
-        apis::candles::Candle, clock, trading::Account, trading::Broker, trading::Position,
+        apis::candles::Candle,
+        backtest::BacktestBroker,
+        clock,
+        engine::{FillAt, Signal},
+        exits::{ExitPolicy, Exits},
+        pdt::{DayTrades, PdtFlag},
+        risk::{RiskLimits, RiskManager, RiskRejection},
+        trading::{Account, Broker, Holding, Position, PriceData, SwingConfig},
     };
 
     #[test]
@@ -186,6 +397,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn next_open_orders_fill_at_the_following_candle() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(BacktestBroker::new(1000.0));
+        acct.fill_at = FillAt::NextOpen;
+        let signal = Candle::new(
+            10.0,
+            10.5,
+            10.6,
+            9.9,
+            100,
+            clock::datetime(2020, 9, 29, 10, 0, 0),
+        );
+        let next = Candle::new(
+            10.8,
+            11.0,
+            11.1,
+            10.7,
+            100,
+            clock::datetime(2020, 9, 29, 10, 1, 0),
+        );
+
+        acct.add_candle(&ticker, &signal);
+        acct.send_order(&ticker, Signal::Buy, 10, signal.close, signal.datetime);
+        assert_eq!(acct.positions.len(), 0);
+
+        acct.add_candle(&ticker, &next);
+        assert_eq!(acct.positions[0].bid, 10.8);
+
+        // the account's own closes don't wait
+        let close = Candle::new(
+            11.0,
+            11.2,
+            11.3,
+            10.9,
+            100,
+            clock::datetime(2020, 9, 29, 15, 55, 0),
+        );
+        acct.add_candle(&ticker, &close);
+        acct.close_position_for_day(&ticker, &close);
+        assert_eq!(acct.positions[0].closes[0].ask, 11.2);
+    }
+
     #[test]
     fn position_provides_return_value() {
         let ticker = "ABC".to_string();
@@ -196,6 +450,24 @@ mod tests {
         assert_eq!(total_return, 10.00);
     }
 
+    #[test]
+    fn cash_account_cannot_open_short_positions() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(SimBroker::new());
+        acct.open_short_position(&ticker, 10.00, 5, clock::datetime(2020, 9, 29, 9, 30, 0));
+        assert_eq!(acct.positions.len(), 0);
+    }
+
+    #[test]
+    fn short_position_returns_the_drop_in_price() {
+        let ticker = "ABC".to_string();
+        let mut position =
+            Position::open(&ticker, -10, 6.00, clock::datetime(2020, 9, 29, 9, 31, 0));
+        position.close(5.00, clock::datetime(2020, 9, 29, 9, 32, 0));
+        assert!(position.is_short());
+        assert_eq!(position.total_return(), 10.00);
+    }
+
     #[test]
     fn market_hours() {
         let broker = SimBroker::new();
@@ -271,4 +543,315 @@ mod tests {
         acct.close_position_for_day(&ticker, &candle);
         assert_eq!(acct.positions[0].open, false);
     }
+
+    fn swing_account<'a>(ticker: &'a String, stop_percent: Option<f64>) -> Account<'a, SimBroker> {
+        let config = SwingConfig {
+            max_holding_days: 3,
+            stop_percent,
+            ..SwingConfig::default()
+        };
+        let mut acct = Account::new(SimBroker::new());
+        acct.holding = Holding::Swing(config);
+        acct.open_position(ticker, 10.00, 10, clock::datetime(2020, 9, 24, 15, 59, 0));
+        acct
+    }
+
+    fn daily(open: f64, close: f64, high: f64, low: f64, day: u32) -> Candle {
+        Candle::new(
+            open,
+            close,
+            high,
+            low,
+            100,
+            clock::datetime(2020, 9, day, 15, 59, 0),
+        )
+    }
+
+    #[test]
+    fn swing_account_holds_positions_overnight() {
+        let ticker = "ABC".to_string();
+        let mut acct = swing_account(&ticker, None);
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.5, 10.6, 9.9, 25));
+        assert!(acct.positions[0].open);
+    }
+
+    #[test]
+    fn swing_account_closes_after_max_holding_days() {
+        let ticker = "ABC".to_string();
+        let mut acct = swing_account(&ticker, None);
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.5, 10.6, 9.9, 25));
+        acct.close_position_for_day(&ticker, &daily(10.5, 10.6, 10.7, 10.4, 28));
+        assert!(acct.positions[0].open);
+
+        acct.close_position_for_day(&ticker, &daily(10.6, 10.8, 10.9, 10.5, 29));
+        assert!(!acct.positions[0].open);
+        assert_eq!(acct.positions[0].closes[0].ask, 10.8);
+    }
+
+    #[test]
+    fn swing_stop_gapped_through_overnight_fills_at_the_open() {
+        let ticker = "ABC".to_string();
+        let mut acct = swing_account(&ticker, Some(5.0));
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.close_position_for_day(&ticker, &daily(9.2, 9.8, 9.9, 9.1, 25));
+        assert!(!acct.positions[0].open);
+        assert_eq!(acct.positions[0].closes[0].ask, 9.2);
+    }
+
+    #[test]
+    fn swing_stop_hit_during_the_day_fills_at_the_stop() {
+        let ticker = "ABC".to_string();
+        let mut acct = swing_account(&ticker, Some(5.0));
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.close_position_for_day(&ticker, &daily(9.8, 9.8, 9.9, 9.4, 25));
+        assert_eq!(acct.positions[0].closes[0].ask, 9.5);
+    }
+
+    #[test]
+    fn large_real_overnight_gap_on_a_swing_position_is_reported_as_a_loss() {
+        let ticker = "ABC".to_string();
+        let mut acct = swing_account(&ticker, Some(5.0));
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.close_position_for_day(&ticker, &daily(7.0, 7.1, 7.2, 6.9, 25));
+
+        let position = &acct.positions[0];
+        assert!(!position.open);
+        assert_eq!(position.closes[0].ask, 7.0);
+        assert!(acct.suspect_gaps.is_empty());
+    }
+
+    #[test]
+    fn split_sized_overnight_gap_closes_a_swing_position_at_the_prior_close() {
+        let ticker = "ABC".to_string();
+        let mut acct = swing_account(&ticker, Some(5.0));
+        acct.close_position_for_day(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.close_position_for_day(&ticker, &daily(5.0, 5.1, 5.2, 4.9, 25));
+
+        let position = &acct.positions[0];
+        assert!(!position.open);
+        assert_eq!(position.closes[0].ask, 10.0);
+        assert_eq!(position.total_return(), 0.0);
+        assert_eq!(acct.suspect_gaps.len(), 1);
+        assert_eq!(acct.suspect_gaps[0].percent, -50.0);
+    }
+
+    #[test]
+    fn split_sized_overnight_gap_does_not_fill_exit_orders() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(SimBroker::new());
+        acct.holding = Holding::Swing(SwingConfig::default());
+        acct.exits = Exits::new(ExitPolicy {
+            stop_percent: Some(5.0),
+            ..ExitPolicy::default()
+        });
+        acct.add_candle(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.open_position(&ticker, 10.0, 10, clock::datetime(2020, 9, 24, 15, 59, 0));
+
+        // a 2:1 split opens far through the stop at 9.50
+        let split = daily(5.0, 5.1, 5.2, 4.9, 25);
+        acct.add_candle(&ticker, &split);
+        acct.close_position_for_day(&ticker, &split);
+        assert!(!acct.is_position_open());
+        assert_eq!(acct.current_position().unwrap().closes[0].ask, 10.0);
+        assert!(acct.exits.orders_for(&ticker).is_none());
+        assert_eq!(acct.suspect_gaps.len(), 1);
+    }
+
+    #[test]
+    fn daily_history_builds_regular_session_bars_at_the_close() {
+        let ticker = "ABC".to_string();
+        let candles = vec![
+            Candle::new(
+                9.0,
+                9.0,
+                9.0,
+                9.0,
+                100,
+                clock::datetime(2020, 9, 24, 8, 0, 0),
+            ),
+            Candle::new(
+                10.0,
+                11.0,
+                11.5,
+                9.5,
+                100,
+                clock::datetime(2020, 9, 24, 9, 30, 0),
+            ),
+            Candle::new(
+                11.0,
+                12.0,
+                12.5,
+                10.5,
+                100,
+                clock::datetime(2020, 9, 24, 15, 59, 0),
+            ),
+            Candle::new(
+                12.0,
+                13.0,
+                13.0,
+                12.0,
+                100,
+                clock::datetime(2020, 9, 25, 9, 30, 0),
+            ),
+        ];
+        let mut price_data = PriceData::with_candles(candles);
+        let history = price_data.daily_history(&ticker, 1).unwrap();
+
+        assert_eq!(history.len(), 1);
+        let day = &history[0];
+        assert_eq!(
+            (day.open, day.close, day.high, day.low),
+            (10.0, 12.0, 12.5, 9.5)
+        );
+        assert_eq!(day.volume, 200);
+        assert_eq!(day.datetime, clock::datetime(2020, 9, 24, 15, 59, 0));
+        assert_eq!(price_data.next_candle().unwrap().close, 13.0);
+    }
+
+    #[test]
+    fn exit_orders_close_positions_before_the_strategy_sees_the_candle() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(SimBroker::new());
+        acct.holding = Holding::Swing(SwingConfig::default());
+        acct.exits = Exits::new(ExitPolicy {
+            stop_percent: Some(5.0),
+            ..ExitPolicy::default()
+        });
+        acct.add_candle(&ticker, &daily(10.0, 10.0, 10.0, 10.0, 24));
+        acct.open_position(&ticker, 10.0, 10, clock::datetime(2020, 9, 24, 15, 59, 0));
+
+        acct.add_candle(&ticker, &daily(9.8, 9.7, 9.9, 9.6, 25));
+        assert!(acct.is_position_open());
+        assert_eq!(acct.exits.orders_for(&ticker).unwrap().stop, Some(9.5));
+
+        acct.add_candle(&ticker, &daily(9.6, 9.4, 9.6, 9.3, 28));
+        assert!(!acct.is_position_open());
+        assert_eq!(acct.current_position().unwrap().closes[0].ask, 9.5);
+        assert!(acct.exits.orders_for(&ticker).is_none());
+    }
+
+    #[test]
+    fn excursions_stop_at_the_exit_fill() {
+        let ticker = "ABC".to_string();
+        let time = clock::datetime(2020, 9, 29, 10, 0, 0);
+        let later = time + clock::Duration::minutes(1);
+        let mut acct = Account::new(BacktestBroker::new(1000.0));
+        acct.exits = Exits::new(ExitPolicy {
+            stop_percent: Some(5.0),
+            target_percent: Some(5.0),
+            ..ExitPolicy::default()
+        });
+
+        acct.add_candle(&ticker, &Candle::new(10.0, 10.0, 10.0, 10.0, 100, time));
+        acct.open_position(&ticker, 10.0, 10, time);
+        acct.add_candle(&ticker, &Candle::new(9.8, 9.2, 9.9, 9.0, 100, later));
+        let stopped = acct.current_position().unwrap();
+        assert_eq!(stopped.closes[0].ask, 9.5);
+        assert_eq!(stopped.mae(), -5.0);
+
+        acct.open_position(&ticker, 10.0, 10, later);
+        let end = later + clock::Duration::minutes(1);
+        acct.add_candle(&ticker, &Candle::new(10.2, 10.8, 11.0, 10.1, 100, end));
+        let targeted = acct.current_position().unwrap();
+        assert_eq!(targeted.closes[0].ask, 10.5);
+        assert_eq!(targeted.mfe(), 5.0);
+    }
+
+    #[test]
+    fn daily_loss_limit_flattens_and_halts_trading() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(SimBroker::new());
+        acct.risk = RiskManager::new(RiskLimits {
+            max_daily_loss: Some(50.0),
+            max_open_positions: Some(1),
+            ..RiskLimits::default()
+        });
+        let time = clock::datetime(2020, 9, 29, 10, 0, 0);
+        acct.open_position(&ticker, 10.0, 10, time);
+        acct.open_position(&ticker, 10.0, 10, time);
+        assert_eq!(
+            acct.risk.rejections[0].reason,
+            RiskRejection::OpenPositions(1)
+        );
+
+        let later = time + clock::Duration::minutes(1);
+        acct.add_candle(&ticker, &Candle::new(6.0, 4.0, 6.0, 4.0, 100, later));
+        assert!(!acct.is_position_open());
+        assert_eq!(acct.current_position().unwrap().closes[0].ask, 4.0);
+        let halt = acct.risk.halted.as_ref().unwrap();
+        assert_eq!((halt.time, halt.profit_and_loss), (later, -60.0));
+
+        acct.open_position(&ticker, 4.0, 10, later);
+        assert_eq!(acct.positions.len(), 1);
+        assert_eq!(acct.risk.rejections[1].reason, RiskRejection::Halted);
+    }
+
+    #[test]
+    fn pattern_day_trader_rule_blocks_a_fourth_day_trade() {
+        let ticker = "ABC".to_string();
+        // SimBroker can't reuse unsettled cash the same day
+        let mut acct = Account::new(BacktestBroker::new(1000.0));
+        acct.day_trades = DayTrades::enforced();
+        for hour in 10..13 {
+            acct.open_position(&ticker, 1.0, 1, clock::datetime(2020, 9, 29, hour, 0, 0));
+            acct.close_position(&ticker, 1.0, clock::datetime(2020, 9, 29, hour, 30, 0));
+        }
+        assert_eq!(acct.day_trades.total(), 3);
+
+        let time = clock::datetime(2020, 9, 29, 14, 0, 0);
+        acct.open_position(&ticker, 1.0, 1, time);
+        assert!(!acct.is_position_open());
+        assert_eq!(
+            acct.day_trades.flags,
+            vec![PdtFlag::Blocked {
+                ticker: ticker.clone(),
+                time
+            }]
+        );
+    }
+
+    #[test]
+    fn margin_buying_power_depends_on_equity_and_session() {
+        let mut broker = MarginBroker::new(10_000.0);
+        assert_eq!(
+            broker.capital(clock::datetime(2020, 9, 29, 10, 0, 0)),
+            20_000.0
+        );
+
+        let mut broker = MarginBroker::new(30_000.0);
+        assert_eq!(
+            broker.capital(clock::datetime(2020, 9, 29, 10, 0, 0)),
+            120_000.0
+        );
+        assert_eq!(
+            broker.capital(clock::datetime(2020, 9, 29, 17, 0, 0)),
+            60_000.0
+        );
+    }
+
+    #[test]
+    fn margin_accounts_pay_interest_and_are_liquidated_below_maintenance() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(MarginBroker::new(10_000.0));
+        let time = clock::datetime(2020, 9, 29, 10, 0, 0);
+        acct.open_position(&ticker, 10.0, 2001, time);
+        assert!(!acct.is_position_open());
+        acct.open_position(&ticker, 10.0, 1500, time);
+        assert!(acct.is_position_open());
+
+        // $5000 borrowed overnight at 8%
+        let next_day = clock::datetime(2020, 9, 30, 10, 0, 0);
+        acct.add_candle(&ticker, &Candle::new(9.0, 9.0, 9.0, 9.0, 100, next_day));
+        assert!((acct.broker.interest_paid - 5000.0 * 0.08 / 360.0).abs() < 1e-9);
+        assert!(acct.is_position_open());
+
+        // equity of about $1000 against a $1500 requirement on $6000 of stock
+        let later = next_day + clock::Duration::minutes(1);
+        acct.add_candle(&ticker, &Candle::new(4.0, 4.0, 4.0, 4.0, 100, later));
+        assert!(!acct.is_position_open());
+        assert_eq!(acct.current_position().unwrap().closes[0].ask, 4.0);
+        assert!(acct.broker.account_equity() < 1000.0);
+        assert_eq!(acct.margin_calls, vec![later]);
+    }
 }
@@ -0,0 +1,261 @@
+use super::{
+    clock,
+    trading::{Account, Broker, Position},
+};
+use serde::Deserialize;
+
+// How many shares to buy for a new position. Every method is also limited to
+// the shares the account's buying power allows.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Sizing {
+    // all available capital
+    #[default]
+    AllIn,
+    FixedDollar {
+        amount: f64,
+    },
+    // lose a percent of equity if the position is stopped out, which needs a
+    // stop or trailing stop in the account's exit policy
+    FixedFractional {
+        risk_percent: f64,
+    },
+    // a move of one ATR is a percent of equity
+    VolatilityTarget {
+        percent: f64,
+        bars: usize,
+    },
+    // a fraction of the Kelly bet estimated from closed trades, never more than
+    // cap_percent of equity. The cap is used until min_trades have closed.
+    Kelly {
+        fraction: f64,
+        cap_percent: f64,
+        min_trades: usize,
+    },
+}
+
+impl Sizing {
+    // e.g. "all", "dollar:500", "risk:1", "volatility:1:14" or "kelly:0.5:20:30"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let error = || format!("invalid sizing: {}", spec);
+        let parts: Vec<String> = spec.split(':').map(|p| p.to_lowercase()).collect();
+        let number = |i: usize| -> Result<f64, String> {
+            parts.get(i).and_then(|p| p.parse().ok()).ok_or_else(error)
+        };
+        let count = |i: usize| -> Result<usize, String> {
+            parts.get(i).and_then(|p| p.parse().ok()).ok_or_else(error)
+        };
+
+        match (parts[0].as_str(), parts.len()) {
+            ("all", 1) => Ok(Sizing::AllIn),
+            ("dollar", 2) => Ok(Sizing::FixedDollar { amount: number(1)? }),
+            ("risk", 2) => Ok(Sizing::FixedFractional {
+                risk_percent: number(1)?,
+            }),
+            ("volatility", 3) => Ok(Sizing::VolatilityTarget {
+                percent: number(1)?,
+                bars: count(2)?,
+            }),
+            ("kelly", 4) => Ok(Sizing::Kelly {
+                fraction: number(1)?,
+                cap_percent: number(2)?,
+                min_trades: count(3)?,
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct SizingPolicy {
+    #[serde(flatten)]
+    pub sizing: Sizing,
+    // limit on the value held in one symbol, as a percent of equity
+    pub max_equity_percent: Option<f64>,
+}
+
+impl SizingPolicy {
+    pub fn shares<B: Broker>(
+        &self,
+        account: &mut Account<B>,
+        ticker: &str,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) -> i32 {
+        let max_shares = account.max_shares(price, time);
+        let equity = account.equity(time);
+
+        let shares = match self.sizing {
+            Sizing::AllIn => max_shares as f64,
+            Sizing::FixedDollar { amount } => amount / price,
+            Sizing::FixedFractional { risk_percent } => {
+                match account.exits.stop_distance(ticker, price) {
+                    Some(distance) if distance > 0.0 => equity * risk_percent / 100.0 / distance,
+                    _ => 0.0,
+                }
+            }
+            Sizing::VolatilityTarget { percent, bars } => match account.atr(ticker, bars) {
+                Some(atr) if atr > 0.0 => equity * percent / 100.0 / atr,
+                _ => 0.0,
+            },
+            Sizing::Kelly {
+                fraction,
+                cap_percent,
+                min_trades,
+            } => {
+                let percent = match kelly_fraction(&account.positions, min_trades) {
+                    Some(kelly) => (kelly * fraction * 100.0).min(cap_percent),
+                    None => cap_percent,
+                };
+                equity * percent / 100.0 / price
+            }
+        };
+
+        let shares = match self.max_equity_percent {
+            Some(percent) => {
+                let held = account
+                    .position_for(ticker)
+                    .map(|position| position.shares.abs() as f64 * position.bid)
+                    .unwrap_or(0.0);
+                let room = (equity * percent / 100.0 - held).max(0.0);
+                shares.min(room / price)
+            }
+            None => shares,
+        };
+        (shares as i32).clamp(0, max_shares.max(0))
+    }
+}
+
+// The Kelly criterion W - (1 - W) / R from closed positions, where W is the
+// win rate and R the average win over the average loss. None until min_trades
+// positions have closed; negative edges return 0.
+pub fn kelly_fraction(positions: &[Position], min_trades: usize) -> Option<f64> {
+    let returns: Vec<f64> = positions
+        .iter()
+        .filter(|position| !position.open)
+        .map(|position| position.total_return())
+        .collect();
+    if returns.len() < min_trades.max(1) {
+        return None;
+    }
+
+    let wins: Vec<f64> = returns.iter().copied().filter(|r| *r > 0.0).collect();
+    let losses: Vec<f64> = returns.iter().copied().filter(|r| *r < 0.0).collect();
+    if wins.is_empty() {
+        return Some(0.0);
+    }
+    if losses.is_empty() {
+        return Some(1.0);
+    }
+
+    let win_rate = wins.len() as f64 / returns.len() as f64;
+    let average_win = wins.iter().sum::<f64>() / wins.len() as f64;
+    let average_loss = -losses.iter().sum::<f64>() / losses.len() as f64;
+    let payoff = average_win / average_loss;
+    Some((win_rate - (1.0 - win_rate) / payoff).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{kelly_fraction, Sizing, SizingPolicy};
+    use crate::{
+        apis::candles::Candle,
+        clock,
+        exits::{ExitPolicy, Exits},
+        simulation::SimBroker,
+        trading::{Account, Position},
+    };
+
+    fn time() -> clock::LocalDateTime {
+        clock::datetime(2020, 9, 29, 10, 0, 0)
+    }
+
+    fn policy(sizing: Sizing) -> SizingPolicy {
+        SizingPolicy {
+            sizing,
+            max_equity_percent: None,
+        }
+    }
+
+    #[test]
+    fn fixed_fractional_risks_a_percent_of_equity_to_the_stop() {
+        let mut acct = Account::new(SimBroker::new());
+        acct.exits = Exits::new(ExitPolicy {
+            stop_percent: Some(2.0),
+            ..ExitPolicy::default()
+        });
+        let sizing = policy(Sizing::FixedFractional { risk_percent: 1.0 });
+        // $10 of risk over a $0.20 stop
+        assert_eq!(sizing.shares(&mut acct, "ABC", 10.0, time()), 50);
+
+        // never more than buying power allows
+        let sizing = policy(Sizing::FixedFractional { risk_percent: 10.0 });
+        assert_eq!(sizing.shares(&mut acct, "ABC", 10.0, time()), 100);
+    }
+
+    #[test]
+    fn volatility_target_sizes_off_recent_candles() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(SimBroker::new());
+        let sizing = policy(Sizing::VolatilityTarget {
+            percent: 1.0,
+            bars: 2,
+        });
+        assert_eq!(sizing.shares(&mut acct, &ticker, 10.0, time()), 0);
+
+        for minute in 0..3 {
+            let time = time() + clock::Duration::minutes(minute);
+            acct.add_candle(&ticker, &Candle::new(10.0, 10.0, 10.25, 9.75, 100, time));
+        }
+        // $10 of equity per ATR of 0.50
+        assert_eq!(sizing.shares(&mut acct, &ticker, 10.0, time()), 20);
+    }
+
+    #[test]
+    fn max_equity_percent_counts_shares_already_held() {
+        let ticker = "ABC".to_string();
+        let mut acct = Account::new(SimBroker::new());
+        let sizing = SizingPolicy {
+            sizing: Sizing::FixedDollar { amount: 300.0 },
+            max_equity_percent: Some(25.0),
+        };
+        assert_eq!(sizing.shares(&mut acct, &ticker, 10.0, time()), 25);
+
+        acct.open_position(&ticker, 10.0, 10, time());
+        assert_eq!(sizing.shares(&mut acct, &ticker, 10.0, time()), 15);
+    }
+
+    #[test]
+    fn kelly_fraction_comes_from_closed_trades() {
+        let ticker = "ABC".to_string();
+        let trade = |ask: f64| {
+            let mut position = Position::open(&ticker, 10, 10.0, time());
+            position.close(ask, time());
+            position
+        };
+        // 2 wins of $20 and 1 loss of $10: W = 2/3, R = 2
+        let positions = vec![trade(12.0), trade(12.0), trade(9.0)];
+        assert_eq!(kelly_fraction(&positions, 5), None);
+        let kelly = kelly_fraction(&positions, 3).unwrap();
+        assert!((kelly - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sizing_is_parsed_from_specs_and_definitions() {
+        assert_eq!(
+            Sizing::parse("VOLATILITY:1:14"),
+            Ok(Sizing::VolatilityTarget {
+                percent: 1.0,
+                bars: 14
+            })
+        );
+        assert!(Sizing::parse("kelly:0.5").is_err());
+
+        let policy: SizingPolicy = toml::from_str(
+            "type = \"fixed_fractional\"\nrisk_percent = 1.0\nmax_equity_percent = 20.0",
+        )
+        .unwrap();
+        assert_eq!(policy.sizing, Sizing::FixedFractional { risk_percent: 1.0 });
+        assert_eq!(policy.max_equity_percent, Some(20.0));
+    }
+}
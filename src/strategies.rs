@@ -1,26 +1,122 @@
 use super::{
-    studies,
-    trading::{Account, Broker, PriceData},
+    clock,
+    engine::{EventStrategy, Signal},
+    exits, rules, studies,
+    timeframes::{Bars, Timeframe},
+    trading::{Account, Broker, PairPriceData},
 };
 use crate::apis::candles::Candle;
 
-pub struct SmaCrossover<'a> {
+// The strategies a backtest can pick with --builtin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Builtin {
+    SmaCrossover,
+    Sma9CrossesSma180,
+    TrendFiltered,
+    OpeningRangeBreakout(OrbStop),
+    MeanReversion,
+    Gap(GapSetup),
+}
+
+impl Builtin {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "sma-crossover" => Ok(Builtin::SmaCrossover),
+            "sma9-crosses-sma180" => Ok(Builtin::Sma9CrossesSma180),
+            "trend-filtered" => Ok(Builtin::TrendFiltered),
+            "orb" | "orb:midpoint" => Ok(Builtin::OpeningRangeBreakout(OrbStop::Midpoint)),
+            "orb:opposite" => Ok(Builtin::OpeningRangeBreakout(OrbStop::OppositeSide)),
+            "mean-reversion" => Ok(Builtin::MeanReversion),
+            "gap-and-go" => Ok(Builtin::Gap(GapSetup::GapAndGo)),
+            "gap-fade" => Ok(Builtin::Gap(GapSetup::GapFade)),
+            _ => Err(format!("invalid strategy: {}", name)),
+        }
+    }
+
+    // Daily bars of history the strategy's studies need to trade swing
+    // positions, None for strategies built around the session's minute bars.
+    pub fn swing_history(&self) -> Option<usize> {
+        match self {
+            Builtin::SmaCrossover => Some(9),
+            Builtin::Sma9CrossesSma180 => Some(180),
+            // its 20 bar trend SMA only counts bars completed by the next one
+            Builtin::TrendFiltered => Some(21),
+            Builtin::MeanReversion => {
+                let config = MeanReversionConfig::default();
+                Some(config.bollinger_bars.max(config.rsi_bars + 1))
+            }
+            Builtin::OpeningRangeBreakout(_) | Builtin::Gap(_) => None,
+        }
+    }
+
+    // The strategy with its default settings, its studies started on candles.
+    pub fn strategy(&self, candles: &[Candle]) -> Box<dyn EventStrategy> {
+        match *self {
+            Builtin::SmaCrossover => Box::new(SmaCrossover::new(candles)),
+            Builtin::Sma9CrossesSma180 => Box::new(Sma9CrossesSma180::new(candles)),
+            Builtin::TrendFiltered => Box::new(TrendFilteredSmaCrossover::new(candles)),
+            Builtin::OpeningRangeBreakout(stop) => {
+                let config = OrbConfig {
+                    stop,
+                    ..OrbConfig::default()
+                };
+                Box::new(OpeningRangeBreakout::new(candles, config))
+            }
+            Builtin::MeanReversion => {
+                let config = MeanReversionConfig::default();
+                Box::new(MeanReversion::new(candles, config))
+            }
+            Builtin::Gap(setup) => {
+                let config = GapConfig {
+                    setup,
+                    ..GapConfig::default()
+                };
+                Box::new(GapTrade::new(candles, config))
+            }
+        }
+    }
+}
+
+pub struct SmaCrossover {
     setup: bool,
     sma9: studies::SMA,
-    ticker: &'a String,
 }
 
-pub struct Sma9CrossesSma180<'a> {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmaCrossConfig {
+    pub fast_bars: usize,
+    pub slow_bars: usize,
+}
+
+impl Default for SmaCrossConfig {
+    fn default() -> Self {
+        Self {
+            fast_bars: 9,
+            slow_bars: 180,
+        }
+    }
+}
+
+pub struct Sma9CrossesSma180 {
+    entry: rules::Condition,
+    exit: rules::Condition,
+}
+
+pub struct TrendFilteredSmaCrossover {
     setup: bool,
     sma9: studies::SMA,
-    sma180: studies::SMA,
-    ticker: &'a String,
+    trend_bars: Bars,
+    trend_sma: studies::SMA,
+    daily_bars: Bars,
+    daily_atr: studies::ATR,
+    day_high: f64,
+    day_low: f64,
 }
 
 // Buy when price closes above SMA9.
 // Sell when price closes below SMA9.
-impl<'a> SmaCrossover<'a> {
-    pub fn new(ticker: &'a String, candles: &[Candle]) -> Self {
+impl SmaCrossover {
+    pub fn new(candles: &[Candle]) -> Self {
         // init studies
         let mut sma9 = studies::SMA::new(9);
         for candle in candles {
@@ -30,7 +126,6 @@ impl<'a> SmaCrossover<'a> {
         Self {
             setup: candles.last().unwrap().close < sma9.value.unwrap(),
             sma9,
-            ticker,
         }
     }
 
@@ -48,50 +143,127 @@ impl<'a> SmaCrossover<'a> {
         let sma9_value = self.sma9.value.unwrap();
         candle.close < sma9_value
     }
+}
 
-    pub fn execute<B: Broker>(&mut self, price_data: &mut PriceData, account: &mut Account<'a, B>) {
-        while let Some(candle) = price_data.next_candle() {
-            self.sma9.add(candle.close);
+impl EventStrategy for SmaCrossover {
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal> {
+        self.sma9.add(candle.close);
 
-            if self.entry_signal(candle) {
-                let shares = account.max_shares(candle.close, candle.datetime);
-                account.open_position(self.ticker, candle.close, shares, candle.datetime);
-                self.setup = false;
-            } else if self.exit_signal(candle) && account.is_position_open() {
-                account.close_position(self.ticker, candle.close, candle.datetime);
-            } else if self.setup_found(candle) && !account.is_position_open() {
+        if self.entry_signal(candle) {
+            self.setup = false;
+            Some(Signal::Buy)
+        } else if self.exit_signal(candle) && open {
+            Some(Signal::Close)
+        } else {
+            if self.setup_found(candle) && !open {
                 self.setup = true;
             }
-
-            account.close_position_for_day(self.ticker, candle);
+            None
         }
     }
 }
 
 // Buy when SMA9 crosses above SMA180.
 // Sell when price closes below SMA9.
-impl<'a> Sma9CrossesSma180<'a> {
-    pub fn new(ticker: &'a String, candles: &[Candle]) -> Self {
+// The lengths can be changed with a SmaCrossConfig, e.g. by the optimizer.
+impl Sma9CrossesSma180 {
+    pub fn new(candles: &[Candle]) -> Self {
+        Self::with_config(candles, SmaCrossConfig::default())
+    }
+
+    pub fn with_config(candles: &[Candle], config: SmaCrossConfig) -> Self {
+        let fast = config.fast_bars;
+        let mut strategy = Self {
+            entry: rules::crosses_above(rules::sma(fast), rules::sma(config.slow_bars)),
+            exit: rules::and(vec![
+                rules::below(rules::close(), rules::sma(fast)),
+                rules::below(rules::close(), rules::open()),
+            ]),
+        };
+
         // init studies
-        let mut sma9 = studies::SMA::new(9);
-        let mut sma180 = studies::SMA::new(180);
         for candle in candles {
-            sma9.add(candle.close);
-            sma180.add(candle.close);
+            strategy.entry.update(candle);
+            strategy.exit.update(candle);
         }
+        strategy
+    }
+}
 
-        Self {
-            setup: sma9.value.unwrap() < sma180.value.unwrap(),
-            sma9,
-            sma180,
-            ticker,
+impl EventStrategy for Sma9CrossesSma180 {
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal> {
+        let entry = self.entry.is_met(candle);
+        let exit = self.exit.is_met(candle);
+
+        if entry && !open {
+            Some(Signal::Buy)
+        } else if exit && open {
+            Some(Signal::Close)
+        } else {
+            None
+        }
+    }
+}
+
+// SmaCrossover entries on 1 minute bars, only taken while price is above the
+// 15 minute SMA20 and the day's range hasn't already used up the daily ATR14.
+// Sell when price closes below SMA9.
+impl TrendFilteredSmaCrossover {
+    pub fn new(candles: &[Candle]) -> Self {
+        let mut strategy = Self {
+            setup: false,
+            sma9: studies::SMA::new(9),
+            trend_bars: Bars::new(Timeframe::Minutes(15)),
+            trend_sma: studies::SMA::new(20),
+            daily_bars: Bars::new(Timeframe::Daily),
+            daily_atr: studies::ATR::new(14),
+            day_high: f64::MIN,
+            day_low: f64::MAX,
+        };
+
+        // init studies
+        for candle in candles {
+            strategy.update_studies(candle);
+        }
+        strategy.setup = candles.last().unwrap().close < strategy.sma9.value.unwrap();
+        strategy
+    }
+
+    fn update_studies(&mut self, candle: &Candle) {
+        self.sma9.add(candle.close);
+        for bar in self.trend_bars.add(candle) {
+            self.trend_sma.add(bar.close);
+        }
+        for bar in self.daily_bars.add(candle) {
+            self.daily_atr.add(bar);
+            self.day_high = f64::MIN;
+            self.day_low = f64::MAX;
+        }
+        self.day_high = self.day_high.max(candle.high);
+        self.day_low = self.day_low.min(candle.low);
+    }
+
+    fn trend_is_up(&self, candle: &Candle) -> bool {
+        match self.trend_sma.value {
+            Some(trend) => candle.close > trend,
+            None => false,
         }
     }
 
-    pub fn entry_signal(&self) -> bool {
+    fn range_remaining(&self) -> bool {
+        match self.daily_atr.value {
+            Some(atr) => self.day_high - self.day_low < atr,
+            None => false,
+        }
+    }
+
+    pub fn entry_signal(&self, candle: &Candle) -> bool {
         let sma9_value = self.sma9.value.unwrap();
-        let sma180_value = self.sma180.value.unwrap();
-        sma9_value > sma180_value && self.setup
+        candle.close > sma9_value
+            && candle.is_bull()
+            && self.setup
+            && self.trend_is_up(candle)
+            && self.range_remaining()
     }
 
     pub fn exit_signal(&self, candle: &Candle) -> bool {
@@ -99,26 +271,1173 @@ impl<'a> Sma9CrossesSma180<'a> {
         candle.close < sma9_value && candle.is_bear()
     }
 
-    pub fn setup_found(&self) -> bool {
-        self.sma9.value.unwrap() < self.sma180.value.unwrap()
+    pub fn setup_found(&self, candle: &Candle) -> bool {
+        let sma9_value = self.sma9.value.unwrap();
+        candle.close < sma9_value
     }
+}
 
-    pub fn execute<B: Broker>(&mut self, price_data: &mut PriceData, account: &mut Account<'a, B>) {
-        while let Some(candle) = price_data.next_candle() {
-            self.sma9.add(candle.close);
-            self.sma180.add(candle.close);
+impl EventStrategy for TrendFilteredSmaCrossover {
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal> {
+        self.update_studies(candle);
 
-            if self.entry_signal() {
-                let shares = account.max_shares(candle.close, candle.datetime);
-                account.open_position(self.ticker, candle.close, shares, candle.datetime);
-                self.setup = false;
-            } else if self.exit_signal(candle) && account.is_position_open() {
-                account.close_position(self.ticker, candle.close, candle.datetime);
-            } else if self.setup_found() && !account.is_position_open() {
+        if self.entry_signal(candle) {
+            self.setup = false;
+            Some(Signal::Buy)
+        } else if self.exit_signal(candle) && open {
+            Some(Signal::Close)
+        } else {
+            if self.setup_found(candle) && !open {
                 self.setup = true;
             }
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrbStop {
+    OppositeSide,
+    Midpoint,
+}
+
+pub struct OrbConfig {
+    pub range_minutes: i64,
+    // breakout candle volume must be this multiple of the range's average volume
+    pub volume_factor: f64,
+    pub stop: OrbStop,
+    // target distance as a multiple of the range size
+    pub target_multiple: f64,
+}
+
+impl Default for OrbConfig {
+    fn default() -> Self {
+        Self {
+            range_minutes: 15,
+            volume_factor: 1.5,
+            stop: OrbStop::Midpoint,
+            target_multiple: 2.0,
+        }
+    }
+}
+
+pub struct OpeningRangeBreakout {
+    config: OrbConfig,
+    opening_range: studies::OpeningRange,
+    session: Option<clock::LocalDate>,
+    range_volume: i64,
+    range_bars: i64,
+    traded_today: bool,
+    short: bool,
+    stop: f64,
+    target: f64,
+}
+
+// Buy when a candle closes above the opening range high on above average volume,
+// or sell short when one closes below the range low. Exit at the stop, the
+// target, or the end of day close. One trade per day.
+impl OpeningRangeBreakout {
+    pub fn new(candles: &[Candle], config: OrbConfig) -> Self {
+        let mut strategy = Self {
+            opening_range: studies::OpeningRange::new(config.range_minutes),
+            config,
+            session: None,
+            range_volume: 0,
+            range_bars: 0,
+            traded_today: false,
+            short: false,
+            stop: 0.0,
+            target: 0.0,
+        };
+
+        // init studies
+        for candle in candles {
+            strategy.update_studies(candle);
+        }
+        strategy
+    }
+
+    fn update_studies(&mut self, candle: &Candle) {
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            self.session = Some(date);
+            self.range_volume = 0;
+            self.range_bars = 0;
+            self.traded_today = false;
+        }
+
+        self.opening_range.add(candle);
+        let range_end =
+            date.and_hms(9, 30, 0) + clock::Duration::minutes(self.config.range_minutes);
+        if clock::is_regular_hours(candle.datetime) && candle.datetime < range_end {
+            self.range_volume += candle.volume;
+            self.range_bars += 1;
+        }
+    }
+
+    // The opening range when the candle can break out of it: the range is set,
+    // nothing has traded today and the candle's volume confirms the move.
+    fn breakout_range(&self, candle: &Candle) -> Option<studies::Range> {
+        let range = self.opening_range.value?;
+        if self.traded_today || self.range_bars == 0 {
+            return None;
+        }
+
+        let average_volume = self.range_volume as f64 / self.range_bars as f64;
+        if candle.volume as f64 >= average_volume * self.config.volume_factor {
+            Some(range)
+        } else {
+            None
+        }
+    }
+
+    pub fn long_signal(&self, candle: &Candle) -> bool {
+        self.breakout_range(candle)
+            .is_some_and(|range| candle.close > range.high)
+    }
+
+    pub fn short_signal(&self, candle: &Candle) -> bool {
+        self.breakout_range(candle)
+            .is_some_and(|range| candle.close < range.low)
+    }
+
+    pub fn exit_price(&self, candle: &Candle, short: bool) -> Option<f64> {
+        exits::fill_price(candle, Some(self.stop), Some(self.target), short)
+    }
+}
+
+impl EventStrategy for OpeningRangeBreakout {
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal> {
+        self.update_studies(candle);
+
+        if open {
+            return self.exit_price(candle, self.short).map(Signal::CloseAt);
+        }
+        if !self.long_signal(candle) && !self.short_signal(candle) {
+            return None;
+        }
+
+        let range = self.opening_range.value.unwrap();
+        self.short = candle.close < range.low;
+        let distance = range.size() * self.config.target_multiple;
+        self.stop = match (self.config.stop, self.short) {
+            (OrbStop::OppositeSide, false) => range.low,
+            (OrbStop::OppositeSide, true) => range.high,
+            (OrbStop::Midpoint, _) => range.midpoint(),
+        };
+        if self.short {
+            self.target = candle.close - distance;
+            Some(Signal::Short)
+        } else {
+            self.target = candle.close + distance;
+            Some(Signal::Buy)
+        }
+    }
+
+    fn on_fill(&mut self, signal: Signal) {
+        if let Signal::Buy | Signal::Short = signal {
+            self.traded_today = true;
+        }
+    }
+}
+
+pub struct MeanReversionConfig {
+    pub bollinger_bars: usize,
+    pub deviations: f64,
+    pub rsi_bars: usize,
+    pub oversold: f64,
+    pub overbought: f64,
+    // exit after this many bars if price hasn't reverted
+    pub time_stop_bars: usize,
+    // also sell short above the upper band when overbought
+    pub allow_short: bool,
+}
+
+impl Default for MeanReversionConfig {
+    fn default() -> Self {
+        Self {
+            bollinger_bars: 20,
+            deviations: 2.0,
+            rsi_bars: 14,
+            oversold: 30.0,
+            overbought: 70.0,
+            time_stop_bars: 30,
+            allow_short: false,
+        }
+    }
+}
+
+pub struct MeanReversion {
+    config: MeanReversionConfig,
+    bollinger: studies::Bollinger,
+    rsi: studies::RSI,
+    bars_held: usize,
+    short: bool,
+}
+
+// Buy when price closes below the lower Bollinger band with RSI oversold.
+// Sell when price closes back at the middle band or after the time stop.
+// Shorts mirror this above the upper band when enabled.
+impl MeanReversion {
+    pub fn new(candles: &[Candle], config: MeanReversionConfig) -> Self {
+        let mut strategy = Self {
+            bollinger: studies::Bollinger::new(config.bollinger_bars, config.deviations),
+            rsi: studies::RSI::new(config.rsi_bars),
+            config,
+            bars_held: 0,
+            short: false,
+        };
+
+        // init studies
+        for candle in candles {
+            strategy.update_studies(candle);
+        }
+        strategy
+    }
+
+    fn update_studies(&mut self, candle: &Candle) {
+        self.bollinger.add(candle.close);
+        self.rsi.add(candle.close);
+    }
+
+    pub fn long_signal(&self, candle: &Candle) -> bool {
+        match (self.bollinger.value, self.rsi.value) {
+            (Some(bands), Some(rsi)) => candle.close < bands.lower && rsi < self.config.oversold,
+            _ => false,
+        }
+    }
+
+    pub fn short_signal(&self, candle: &Candle) -> bool {
+        match (self.bollinger.value, self.rsi.value) {
+            (Some(bands), Some(rsi)) => {
+                self.config.allow_short
+                    && candle.close > bands.upper
+                    && rsi > self.config.overbought
+            }
+            _ => false,
+        }
+    }
+
+    pub fn exit_signal(&self, candle: &Candle, short: bool) -> bool {
+        let middle = match self.bollinger.value {
+            Some(bands) => bands.middle,
+            None => return false,
+        };
+        let reverted = if short {
+            candle.close <= middle
+        } else {
+            candle.close >= middle
+        };
+        reverted || self.bars_held >= self.config.time_stop_bars
+    }
+}
+
+impl EventStrategy for MeanReversion {
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal> {
+        self.update_studies(candle);
+
+        if open {
+            self.bars_held += 1;
+            if self.exit_signal(candle, self.short) {
+                return Some(Signal::Close);
+            }
+            return None;
+        }
+
+        self.short = if self.long_signal(candle) {
+            false
+        } else if self.short_signal(candle) {
+            true
+        } else {
+            return None;
+        };
+        self.bars_held = 0;
+        Some(if self.short {
+            Signal::Short
+        } else {
+            Signal::Buy
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GapSetup {
+    // trade a break of the premarket range in the direction of the gap
+    GapAndGo,
+    // trade a failure of the premarket range back toward the prior close
+    GapFade,
+}
+
+pub struct GapConfig {
+    pub setup: GapSetup,
+    pub min_gap_percent: f64,
+    pub min_premarket_volume: i64,
+    // gap and go target as a multiple of the stop distance
+    pub target_multiple: f64,
+}
+
+impl Default for GapConfig {
+    fn default() -> Self {
+        Self {
+            setup: GapSetup::GapAndGo,
+            min_gap_percent: 4.0,
+            min_premarket_volume: 50_000,
+            target_multiple: 2.0,
+        }
+    }
+}
+
+pub struct GapTrade {
+    config: GapConfig,
+    premarket: studies::Premarket,
+    session: Option<clock::LocalDate>,
+    day_high: f64,
+    day_low: f64,
+    traded_today: bool,
+    short: bool,
+    stop: f64,
+    target: f64,
+}
+
+// Gap and go: on a gap up, buy a close above the premarket high with the stop
+// at the day's low; gap downs are sold short below the premarket low.
+// Gap fade: on a gap up, sell short a close below the premarket low targeting
+// the prior close with the stop at the premarket high; gap downs mirror it.
+// Both need premarket volume, take one trade per day and are flat by the close.
+impl GapTrade {
+    pub fn new(candles: &[Candle], config: GapConfig) -> Self {
+        let mut strategy = Self {
+            config,
+            premarket: studies::Premarket::new(),
+            session: None,
+            day_high: f64::MIN,
+            day_low: f64::MAX,
+            traded_today: false,
+            short: false,
+            stop: 0.0,
+            target: 0.0,
+        };
+
+        // init studies
+        for candle in candles {
+            strategy.update_studies(candle);
+        }
+        strategy
+    }
+
+    fn update_studies(&mut self, candle: &Candle) {
+        self.premarket.add(candle);
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            self.session = Some(date);
+            self.day_high = f64::MIN;
+            self.day_low = f64::MAX;
+            self.traded_today = false;
+        }
+        if clock::is_regular_hours(candle.datetime) {
+            self.day_high = self.day_high.max(candle.high);
+            self.day_low = self.day_low.min(candle.low);
+        }
+    }
+
+    // Returns whether to go short along with the stop and target for an entry.
+    pub fn entry_signal(&self, candle: &Candle) -> Option<(bool, f64, f64)> {
+        let levels = self.premarket.value?;
+        if self.traded_today
+            || !clock::is_regular_hours(candle.datetime)
+            || levels.volume < self.config.min_premarket_volume
+        {
+            return None;
+        }
+
+        let gap_up = levels.gap_percent >= self.config.min_gap_percent;
+        let gap_down = levels.gap_percent <= -self.config.min_gap_percent;
+        let multiple = self.config.target_multiple;
+        match self.config.setup {
+            GapSetup::GapAndGo if gap_up && candle.close > levels.high => {
+                let stop = self.day_low;
+                Some((false, stop, candle.close + (candle.close - stop) * multiple))
+            }
+            GapSetup::GapAndGo if gap_down && candle.close < levels.low => {
+                let stop = self.day_high;
+                Some((true, stop, candle.close - (stop - candle.close) * multiple))
+            }
+            GapSetup::GapFade if gap_up && candle.close < levels.low => {
+                Some((true, levels.high, levels.prior_close))
+            }
+            GapSetup::GapFade if gap_down && candle.close > levels.high => {
+                Some((false, levels.low, levels.prior_close))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl EventStrategy for GapTrade {
+    fn on_candle(&mut self, candle: &Candle, open: bool) -> Option<Signal> {
+        self.update_studies(candle);
+
+        if open {
+            let (stop, target) = (Some(self.stop), Some(self.target));
+            return exits::fill_price(candle, stop, target, self.short).map(Signal::CloseAt);
+        }
+
+        let (short, stop, target) = self.entry_signal(candle)?;
+        self.short = short;
+        self.stop = stop;
+        self.target = target;
+        Some(if short { Signal::Short } else { Signal::Buy })
+    }
+
+    fn on_fill(&mut self, signal: Signal) {
+        if let Signal::Buy | Signal::Short = signal {
+            self.traded_today = true;
+        }
+    }
+}
+
+pub struct PairsConfig {
+    // bars in the rolling hedge ratio regression
+    pub bars: usize,
+    pub entry_z: f64,
+    pub exit_z: f64,
+}
+
+impl Default for PairsConfig {
+    fn default() -> Self {
+        Self {
+            bars: 60,
+            entry_z: 2.0,
+            exit_z: 0.5,
+        }
+    }
+}
+
+pub struct PairsTrade<'a> {
+    config: PairsConfig,
+    spread: studies::SpreadZScore,
+    ticker_x: &'a String,
+    ticker_y: &'a String,
+}
+
+// Trades the spread of y regressed on x. When the spread's z-score is above
+// entry_z, y is sold short and x bought; below -entry_z, y is bought and x
+// sold short. Both legs are closed once the z-score is back within exit_z.
+// The account's sizing policy sizes the pair, which is split into equal dollar
+// legs so it's dollar neutral.
+impl<'a> PairsTrade<'a> {
+    pub fn new(
+        tickers: (&'a String, &'a String),
+        pairs: &[(Candle, Candle)],
+        config: PairsConfig,
+    ) -> Self {
+        let mut spread = studies::SpreadZScore::new(config.bars);
+        // init studies
+        for (x, y) in pairs {
+            spread.add(x.close, y.close);
+        }
+
+        Self {
+            config,
+            spread,
+            ticker_x: tickers.0,
+            ticker_y: tickers.1,
+        }
+    }
+
+    fn open_pair<B: Broker>(
+        &self,
+        account: &mut Account<'a, B>,
+        long: (&'a String, &Candle),
+        short: (&'a String, &Candle),
+    ) {
+        let time = long.1.datetime;
+        let shares = account.position_size(long.0, long.1.close, time);
+        let dollars_per_leg = shares as f64 * long.1.close / 2.0;
+        let long_shares = (dollars_per_leg / long.1.close) as i32;
+        let short_shares = (dollars_per_leg / short.1.close) as i32;
+        if long_shares <= 0 || short_shares <= 0 {
+            return;
+        }
+
+        account.send_order(short.0, Signal::Short, short_shares, short.1.close, time);
+        if !account.has_position_or_order(short.0) {
+            return;
+        }
+        account.send_order(long.0, Signal::Buy, long_shares, long.1.close, time);
+        if !account.has_position_or_order(long.0) {
+            // never hold one leg on its own
+            account.send_order(short.0, Signal::Close, 0, short.1.close, time);
+        }
+    }
+
+    fn close_pair<B: Broker>(&self, account: &mut Account<'a, B>, x: &Candle, y: &Candle) {
+        account.send_order(self.ticker_x, Signal::Close, 0, x.close, x.datetime);
+        account.send_order(self.ticker_y, Signal::Close, 0, y.close, y.datetime);
+    }
+
+    pub fn execute<B: Broker>(
+        &mut self,
+        price_data: &mut PairPriceData,
+        account: &mut Account<'a, B>,
+    ) {
+        while let Some((x, y)) = price_data.next_pair() {
+            account.add_candle(self.ticker_x, x);
+            account.add_candle(self.ticker_y, y);
+            let legs_open = (
+                account.position_for(self.ticker_x).is_some(),
+                account.position_for(self.ticker_y).is_some(),
+            );
+            if legs_open.0 != legs_open.1 {
+                // an exit order closed one leg, never hold the other on its own
+                self.close_pair(account, x, y);
+            }
+
+            self.spread.add(x.close, y.close);
+            let in_trade = account.position_for(self.ticker_y).is_some();
+
+            if let Some(z) = self.spread.value {
+                if in_trade && z.abs() <= self.config.exit_z {
+                    self.close_pair(account, x, y);
+                } else if !in_trade && z > self.config.entry_z {
+                    self.open_pair(account, (self.ticker_x, x), (self.ticker_y, y));
+                } else if !in_trade && z < -self.config.entry_z {
+                    self.open_pair(account, (self.ticker_y, y), (self.ticker_x, x));
+                }
+            }
+
+            account.close_position_for_day(self.ticker_x, x);
+            account.close_position_for_day(self.ticker_y, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        GapConfig, GapSetup, GapTrade, MeanReversion, MeanReversionConfig, OpeningRangeBreakout,
+        OrbConfig, OrbStop, PairsConfig, PairsTrade, SmaCrossover, TrendFilteredSmaCrossover,
+    };
+    use crate::{
+        apis::candles::{candle, Candle},
+        backtest::BacktestBroker,
+        clock,
+        engine::{self, EngineConfig, EventStrategy, FillAt, Signal},
+        exits::{ExitPolicy, Exits},
+        simulation::SimBroker,
+        trading::{Account, PairPriceData, PriceData},
+    };
+
+    // Two 1 minute opening range bars between 10.00 and 11.00 on 1000 shares each.
+    fn opening_range(day: u32) -> Vec<Candle> {
+        vec![
+            Candle::new(
+                10.2,
+                10.8,
+                11.0,
+                10.0,
+                1000,
+                clock::datetime(2020, 9, day, 9, 30, 0),
+            ),
+            Candle::new(
+                10.8,
+                10.4,
+                10.9,
+                10.2,
+                1000,
+                clock::datetime(2020, 9, day, 9, 31, 0),
+            ),
+        ]
+    }
+
+    fn config() -> OrbConfig {
+        OrbConfig {
+            range_minutes: 2,
+            volume_factor: 1.5,
+            stop: OrbStop::Midpoint,
+            target_multiple: 2.0,
+        }
+    }
+
+    // BacktestBroker since a cash account can't sell short
+    fn run(
+        ticker: &String,
+        candles: Vec<Candle>,
+        config: OrbConfig,
+    ) -> Account<'_, BacktestBroker> {
+        let mut price_data = PriceData::with_candles(candles);
+        let history = price_data.history(ticker, 0, "1:minute").unwrap();
+        let mut strategy = OpeningRangeBreakout::new(history, config);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        engine::run_strategy(
+            ticker,
+            &mut strategy,
+            &mut price_data,
+            &mut account,
+            EngineConfig::default(),
+        );
+        account
+    }
+
+    #[test]
+    fn breakout_without_volume_confirmation_is_ignored() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 11.2, 11.3, 10.9, 1000, 2));
+        let account = run(&ticker, candles, config());
+        assert_eq!(account.positions.len(), 0);
+    }
+
+    #[test]
+    fn breakout_closing_inside_the_range_is_ignored() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 10.95, 11.3, 10.9, 5000, 2));
+        let account = run(&ticker, candles, config());
+        assert_eq!(account.positions.len(), 0);
+    }
+
+    #[test]
+    fn breakout_is_stopped_at_the_range_midpoint() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 11.2, 11.3, 10.9, 2000, 2));
+        candles.push(candle(11.1, 10.6, 11.1, 10.4, 1000, 3));
+        let account = run(&ticker, candles, config());
+
+        assert_eq!(account.positions.len(), 1);
+        let position = &account.positions[0];
+        assert_eq!(position.bid, 11.2);
+        assert_eq!(position.closes[0].ask, 10.5);
+    }
+
+    #[test]
+    fn breakout_filled_at_the_next_open_is_stopped_at_the_midpoint() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 11.2, 11.3, 10.9, 2000, 2));
+        candles.push(candle(11.1, 10.6, 11.1, 10.4, 1000, 3));
+        let mut price_data = PriceData::with_candles(candles);
+        let history = price_data.history(&ticker, 0, "1:minute").unwrap();
+        let mut strategy = OpeningRangeBreakout::new(history, config());
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        let next_open = EngineConfig {
+            fill_at: FillAt::NextOpen,
+            ..EngineConfig::default()
+        };
+        engine::run_strategy(
+            &ticker,
+            &mut strategy,
+            &mut price_data,
+            &mut account,
+            next_open,
+        );
+
+        // the stop rests at the broker, so it doesn't wait for another open
+        let position = &account.positions[0];
+        assert_eq!(position.bid, 11.1);
+        assert_eq!(position.closes[0].ask, 10.5);
+    }
+
+    #[test]
+    fn breakout_is_stopped_at_the_opposite_side() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 11.2, 11.3, 10.9, 2000, 2));
+        candles.push(candle(11.1, 10.6, 11.1, 10.4, 1000, 3));
+        candles.push(candle(10.6, 10.1, 10.6, 9.9, 1000, 4));
+        let config = OrbConfig {
+            stop: OrbStop::OppositeSide,
+            ..config()
+        };
+        let account = run(&ticker, candles, config);
+
+        assert_eq!(account.positions[0].closes[0].ask, 10.0);
+    }
+
+    #[test]
+    fn breakout_takes_profit_at_a_multiple_of_the_range() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 11.2, 11.3, 10.9, 2000, 2));
+        candles.push(candle(11.3, 13.0, 13.5, 11.3, 1000, 3));
+        let account = run(&ticker, candles, config());
+
+        assert_eq!(account.positions[0].closes[0].ask, 13.2);
+    }
+
+    #[test]
+    fn breakdown_is_sold_short_and_stopped_at_the_range_midpoint() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.1, 9.8, 10.1, 9.7, 2000, 2));
+        candles.push(candle(9.9, 10.4, 10.6, 9.9, 1000, 3));
+        let account = run(&ticker, candles, config());
+
+        assert_eq!(account.positions.len(), 1);
+        let position = &account.positions[0];
+        assert!(position.is_short());
+        assert_eq!(position.bid, 9.8);
+        assert_eq!(position.closes[0].ask, 10.5);
+    }
+
+    #[test]
+    fn breakdown_is_stopped_at_the_opposite_side() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.1, 9.8, 10.1, 9.7, 2000, 2));
+        candles.push(candle(9.9, 10.4, 10.6, 9.9, 1000, 3));
+        candles.push(candle(10.4, 10.9, 11.1, 10.3, 1000, 4));
+        let config = OrbConfig {
+            stop: OrbStop::OppositeSide,
+            ..config()
+        };
+        let account = run(&ticker, candles, config);
+
+        assert_eq!(account.positions[0].closes[0].ask, 11.0);
+    }
+
+    #[test]
+    fn breakdown_takes_profit_at_a_multiple_of_the_range() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.1, 9.5, 10.1, 9.4, 2000, 2));
+        candles.push(candle(9.4, 7.6, 9.4, 7.0, 1000, 3));
+        let account = run(&ticker, candles, config());
+
+        let position = &account.positions[0];
+        assert_eq!(position.closes[0].ask, 7.5);
+        assert!(position.total_return() > 0.0);
+    }
+
+    #[test]
+    fn breakout_is_flat_by_the_end_of_day_and_trades_once() {
+        let ticker = "ABC".to_string();
+        let mut candles = opening_range(29);
+        candles.push(candle(10.9, 11.2, 11.3, 10.9, 2000, 2));
+        candles.push(candle(11.2, 11.4, 11.5, 11.1, 1000, 385));
+        candles.push(candle(11.4, 11.6, 11.7, 11.3, 5000, 386));
+        let account = run(&ticker, candles, config());
+
+        assert_eq!(account.positions.len(), 1);
+        assert!(!account.positions[0].open);
+        assert_eq!(account.positions[0].closes[0].ask, 11.4);
+    }
+
+    fn closes(prices: &[f64]) -> Vec<Candle> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, price)| candle(*price, *price, *price, *price, 100, i as i64))
+            .collect()
+    }
+
+    fn mean_reversion_config() -> MeanReversionConfig {
+        MeanReversionConfig {
+            bollinger_bars: 4,
+            deviations: 1.0,
+            rsi_bars: 2,
+            oversold: 30.0,
+            overbought: 70.0,
+            time_stop_bars: 3,
+            allow_short: false,
+        }
+    }
+
+    fn run_mean_reversion<'a>(
+        ticker: &'a String,
+        prices: &[f64],
+        config: MeanReversionConfig,
+    ) -> Account<'a, BacktestBroker> {
+        let mut price_data = PriceData::with_candles(closes(prices));
+        let history = price_data.history(ticker, 0, "1:minute").unwrap();
+        let mut strategy = MeanReversion::new(history, config);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        engine::run_strategy(
+            ticker,
+            &mut strategy,
+            &mut price_data,
+            &mut account,
+            EngineConfig::default(),
+        );
+        account
+    }
+
+    #[test]
+    fn mean_reversion_buys_oversold_below_the_band_and_exits_at_the_middle() {
+        let ticker = "ABC".to_string();
+        let account = run_mean_reversion(
+            &ticker,
+            &[10.0, 10.0, 10.0, 10.0, 8.0, 8.5, 9.8],
+            mean_reversion_config(),
+        );
+
+        assert_eq!(account.positions.len(), 1);
+        let position = &account.positions[0];
+        assert_eq!(position.bid, 8.0);
+        assert_eq!(position.closes[0].ask, 9.8);
+    }
+
+    #[test]
+    fn mean_reversion_exits_on_the_time_stop() {
+        let ticker = "ABC".to_string();
+        let account = run_mean_reversion(
+            &ticker,
+            &[10.0, 10.0, 10.0, 10.0, 8.0, 7.0, 6.0, 5.5, 5.0],
+            mean_reversion_config(),
+        );
+
+        assert_eq!(account.positions[0].bid, 8.0);
+        assert_eq!(account.positions[0].closes[0].ask, 5.5);
+    }
+
+    #[test]
+    fn mean_reversion_only_shorts_when_enabled() {
+        let ticker = "ABC".to_string();
+        let prices = [10.0, 10.0, 10.0, 10.0, 12.0, 11.0, 10.0];
+        let account = run_mean_reversion(&ticker, &prices, mean_reversion_config());
+        assert_eq!(account.positions.len(), 0);
+
+        let config = MeanReversionConfig {
+            allow_short: true,
+            ..mean_reversion_config()
+        };
+        let account = run_mean_reversion(&ticker, &prices, config);
+        let position = &account.positions[0];
+        assert!(position.is_short());
+        assert_eq!(position.bid, 12.0);
+        assert_eq!(position.closes[0].ask, 10.0);
+        assert_eq!(position.total_return(), 2.0 * 83.0);
+    }
+
+    #[test]
+    fn pairs_trade_holds_dollar_neutral_legs_until_the_spread_reverts() {
+        let x_ticker = "XXX".to_string();
+        let y_ticker = "YYY".to_string();
+        let x_prices = [10.0, 11.0, 10.0, 11.0, 10.0, 11.0];
+        let y_prices = [20.0, 22.0, 20.0, 22.0, 23.0, 22.0];
+        let mut price_data = PairPriceData::with_candles(closes(&x_prices), closes(&y_prices));
+        let history = price_data
+            .history((&x_ticker, &y_ticker), 4, "1:minute")
+            .unwrap();
+        let config = PairsConfig {
+            bars: 4,
+            entry_z: 1.2,
+            exit_z: 0.5,
+        };
+        let mut strategy = PairsTrade::new((&x_ticker, &y_ticker), history, config);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        strategy.execute(&mut price_data, &mut account);
+
+        assert_eq!(account.positions.len(), 2);
+        let short_y = &account.positions[0];
+        let long_x = &account.positions[1];
+        assert_eq!(short_y.ticker, &y_ticker);
+        assert_eq!(short_y.shares, -21);
+        assert_eq!(long_x.ticker, &x_ticker);
+        assert_eq!(long_x.shares, 50);
+        assert!(!short_y.open && !long_x.open);
+        assert_eq!(short_y.closes[0].ask, 22.0);
+        assert_eq!(long_x.closes[0].ask, 11.0);
+    }
 
-            account.close_position_for_day(self.ticker, candle);
+    #[test]
+    fn pairs_trade_can_fill_at_the_next_open() {
+        let x_ticker = "XXX".to_string();
+        let y_ticker = "YYY".to_string();
+        let x_prices = [10.0, 11.0, 10.0, 11.0, 10.0, 10.5, 11.0];
+        let y_prices = [20.0, 22.0, 20.0, 22.0, 23.0, 22.5, 22.0];
+        let mut price_data = PairPriceData::with_candles(closes(&x_prices), closes(&y_prices));
+        let history = price_data
+            .history((&x_ticker, &y_ticker), 4, "1:minute")
+            .unwrap();
+        let config = PairsConfig {
+            bars: 4,
+            entry_z: 1.2,
+            exit_z: 0.5,
+        };
+        let mut strategy = PairsTrade::new((&x_ticker, &y_ticker), history, config);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        account.fill_at = FillAt::NextOpen;
+        strategy.execute(&mut price_data, &mut account);
+
+        // both legs fill at the open after the signal bar, x's candle first
+        let long_x = &account.positions[0];
+        let short_y = &account.positions[1];
+        assert_eq!((long_x.shares, long_x.bid), (50, 10.5));
+        assert_eq!((short_y.shares, short_y.bid), (-21, 22.5));
+        assert_eq!(long_x.closes[0].ask, 11.0);
+        assert_eq!(short_y.closes[0].ask, 22.0);
+    }
+
+    #[test]
+    fn pairs_trade_closes_both_legs_when_one_is_stopped_out() {
+        let x_ticker = "XXX".to_string();
+        let y_ticker = "YYY".to_string();
+        let x_prices = [10.0, 11.0, 10.0, 11.0, 10.0, 10.2];
+        let y_prices = [20.0, 22.0, 20.0, 22.0, 23.0, 25.0];
+        let mut price_data = PairPriceData::with_candles(closes(&x_prices), closes(&y_prices));
+        let history = price_data
+            .history((&x_ticker, &y_ticker), 4, "1:minute")
+            .unwrap();
+        let config = PairsConfig {
+            bars: 4,
+            entry_z: 1.2,
+            exit_z: 0.5,
+        };
+        let mut strategy = PairsTrade::new((&x_ticker, &y_ticker), history, config);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        account.exits = Exits::new(ExitPolicy {
+            stop_percent: Some(5.0),
+            ..ExitPolicy::default()
+        });
+        strategy.execute(&mut price_data, &mut account);
+
+        let short_y = &account.positions[0];
+        let long_x = &account.positions[1];
+        assert_eq!(short_y.closes[0].ask, 25.0);
+        assert_eq!(long_x.closes[0].ask, 10.2);
+        assert_eq!(short_y.mae(), -21.0 * 2.0);
+    }
+
+    #[test]
+    fn align_candles_drops_unmatched_timestamps() {
+        let a = closes(&[1.0, 2.0, 3.0, 4.0]);
+        let mut b = closes(&[5.0, 6.0, 7.0, 8.0]);
+        b.remove(1);
+        let pairs = crate::trading::align_candles(a, b);
+        let closes: Vec<(f64, f64)> = pairs.iter().map(|(a, b)| (a.close, b.close)).collect();
+        assert_eq!(closes, vec![(1.0, 5.0), (3.0, 7.0), (4.0, 8.0)]);
+    }
+
+    // Prior close of 10.00 and a premarket between 10.80 and 11.20 on 60k shares.
+    fn gap_up_fixture() -> Vec<Candle> {
+        vec![
+            Candle::new(
+                10.1,
+                10.0,
+                10.2,
+                9.9,
+                1000,
+                clock::datetime(2020, 9, 28, 15, 59, 0),
+            ),
+            Candle::new(
+                10.9,
+                11.0,
+                11.2,
+                10.8,
+                30_000,
+                clock::datetime(2020, 9, 29, 7, 0, 0),
+            ),
+            Candle::new(
+                11.0,
+                11.1,
+                11.1,
+                10.9,
+                30_000,
+                clock::datetime(2020, 9, 29, 9, 0, 0),
+            ),
+        ]
+    }
+
+    fn run_gap<'a>(
+        ticker: &'a String,
+        candles: Vec<Candle>,
+        config: GapConfig,
+    ) -> Account<'a, BacktestBroker> {
+        let mut price_data = PriceData::with_candles(candles);
+        let history = price_data.history(ticker, 0, "1:minute").unwrap();
+        let mut strategy = GapTrade::new(history, config);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        engine::run_strategy(
+            ticker,
+            &mut strategy,
+            &mut price_data,
+            &mut account,
+            EngineConfig::default(),
+        );
+        account
+    }
+
+    #[test]
+    fn gap_and_go_buys_the_premarket_high_break() {
+        let ticker = "ABC".to_string();
+        let mut candles = gap_up_fixture();
+        candles.push(candle(11.0, 11.1, 11.15, 10.9, 1000, 0));
+        candles.push(candle(11.1, 11.3, 11.3, 11.1, 1000, 1));
+        candles.push(candle(11.3, 11.8, 11.9, 11.3, 1000, 2));
+        candles.push(candle(11.8, 12.0, 12.2, 11.8, 1000, 3));
+        let account = run_gap(&ticker, candles, GapConfig::default());
+
+        let position = &account.positions[0];
+        assert!(!position.is_short());
+        assert_eq!(position.bid, 11.3);
+        // stop at the day's low of 10.90 puts a 2R target at 12.10
+        assert!((position.closes[0].ask - 12.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gap_and_go_needs_premarket_volume() {
+        let ticker = "ABC".to_string();
+        let mut candles = gap_up_fixture();
+        candles.push(candle(11.1, 11.3, 11.3, 11.1, 1000, 1));
+        let config = GapConfig {
+            min_premarket_volume: 100_000,
+            ..GapConfig::default()
+        };
+        let account = run_gap(&ticker, candles, config);
+        assert_eq!(account.positions.len(), 0);
+    }
+
+    #[test]
+    fn gap_fade_shorts_the_premarket_low_break_toward_the_prior_close() {
+        let ticker = "ABC".to_string();
+        let mut candles = gap_up_fixture();
+        candles.push(candle(11.0, 10.7, 11.0, 10.6, 1000, 0));
+        candles.push(candle(10.7, 10.3, 10.7, 10.2, 1000, 1));
+        candles.push(candle(10.3, 10.1, 10.3, 9.95, 1000, 2));
+        let config = GapConfig {
+            setup: GapSetup::GapFade,
+            ..GapConfig::default()
+        };
+        let account = run_gap(&ticker, candles, config);
+
+        assert_eq!(account.positions.len(), 1);
+        let position = &account.positions[0];
+        assert!(position.is_short());
+        assert_eq!(position.bid, 10.7);
+        assert_eq!(position.closes[0].ask, 10.0);
+    }
+
+    // Nine bars that finish below their SMA9, a bull bar closing above it and
+    // one more bar.
+    fn sma_crossover_entry(config: EngineConfig) -> f64 {
+        let ticker = "ABC".to_string();
+        let mut candles: Vec<Candle> = (0..9)
+            .map(|m| {
+                let close = if m == 8 { 9.5 } else { 10.0 };
+                candle(10.0, close, 10.0, close, 1000, 30 + m)
+            })
+            .collect();
+        candles.push(candle(9.6, 10.5, 10.5, 9.6, 1000, 39));
+        candles.push(candle(10.6, 10.7, 10.7, 10.6, 1000, 40));
+
+        let mut price_data = PriceData::with_candles(candles);
+        let history = price_data.history(&ticker, 9, "1:minute").unwrap();
+        let mut strategy = SmaCrossover::new(history);
+        let mut account = Account::new(BacktestBroker::new(1000.0));
+        engine::run_strategy(
+            &ticker,
+            &mut strategy,
+            &mut price_data,
+            &mut account,
+            config,
+        );
+        // every candle has been seen, so this isn't looking ahead
+        assert_eq!(price_data.traded_candles().len(), 2);
+        // the bar after the entry only went its way
+        assert_eq!(account.positions[0].mae(), 0.0);
+        assert!(account.positions[0].mfe() > 0.0);
+        account.positions[0].bid
+    }
+
+    #[test]
+    fn sma_crossover_can_fill_at_the_next_open() {
+        assert_eq!(sma_crossover_entry(EngineConfig::default()), 10.5);
+        let next_open = EngineConfig {
+            fill_at: FillAt::NextOpen,
+            ..EngineConfig::default()
+        };
+        assert_eq!(sma_crossover_entry(next_open), 10.6);
+    }
+
+    #[test]
+    #[should_panic(expected = "look-ahead")]
+    fn reading_candles_not_yet_seen_panics() {
+        let ticker = "ABC".to_string();
+        let candles = (0..3)
+            .map(|m| candle(10.0, 10.0, 10.0, 10.0, 1000, 30 + m))
+            .collect();
+        let mut price_data = PriceData::with_candles(candles);
+        price_data.history(&ticker, 1, "1:minute");
+        price_data.next_candle();
+        price_data.traded_candles();
+    }
+
+    #[test]
+    #[should_panic(expected = "look-ahead")]
+    fn trading_at_a_candle_not_yet_seen_panics() {
+        let ticker = "ABC".to_string();
+        let mut price_data = PriceData::with_candles(
+            (0..3)
+                .map(|m| candle(10.0, 10.0, 10.0, 10.0, 1000, 30 + m))
+                .collect(),
+        );
+        let mut account = Account::new(SimBroker::new());
+        let seen = price_data.next_candle().unwrap().clone();
+        account.add_candle(&ticker, &seen);
+        let ahead = price_data.next_candle().unwrap();
+        account.open_position(&ticker, ahead.close, 10, ahead.datetime);
+    }
+
+    // Fifteen days of four 15 minute candles from 9:30, each day's closes
+    // starting `step` from the last and rising 0.10 a candle. Every day's range,
+    // and so the daily ATR, is 1.30.
+    fn trend_history(start: f64, step: f64) -> Vec<Candle> {
+        let mut candles = Vec::new();
+        for day in 1..=15 {
+            let base = start + step * day as f64;
+            for bar in 0..4 {
+                let close = base + 0.1 * bar as f64;
+                let minutes = 9 * 60 + 30 + 15 * bar;
+                let time = clock::datetime(2020, 9, day, minutes / 60, minutes % 60, 0);
+                candles.push(Candle::new(
+                    close,
+                    close,
+                    close + 0.5,
+                    close - 0.5,
+                    100,
+                    time,
+                ));
+            }
         }
+        candles
+    }
+
+    // Feeds the day's candles to the strategy without a position, returning
+    // whether it would have bought.
+    fn trend_filtered_enters(history: &[Candle], day: &[Candle]) -> bool {
+        let mut strategy = TrendFilteredSmaCrossover::new(history);
+        day.iter()
+            .any(|candle| strategy.on_candle(candle, false) == Some(Signal::Buy))
+    }
+
+    #[test]
+    fn trend_filtered_crossover_buys_a_dip_in_an_uptrend() {
+        let day = [
+            candle(17.6, 17.2, 17.7, 17.1, 100, 0),
+            candle(17.2, 17.7, 17.8, 17.1, 100, 1),
+        ];
+        assert!(trend_filtered_enters(&trend_history(10.0, 0.5), &day));
+    }
+
+    #[test]
+    fn trend_filtered_crossover_skips_a_dip_below_the_higher_timeframe_trend() {
+        // above SMA9 but below the 15 minute SMA20
+        let day = [
+            candle(12.9, 12.6, 13.0, 12.5, 100, 0),
+            candle(12.6, 13.2, 13.3, 12.5, 100, 1),
+        ];
+        assert!(!trend_filtered_enters(&trend_history(20.0, -0.5), &day));
+    }
+
+    #[test]
+    fn trend_filtered_crossover_skips_a_day_that_used_up_its_atr() {
+        let day = [
+            candle(17.6, 17.2, 17.7, 15.5, 100, 0),
+            candle(17.2, 17.7, 17.8, 17.1, 100, 1),
+        ];
+        assert!(!trend_filtered_enters(&trend_history(10.0, 0.5), &day));
     }
 }
@@ -1,3 +1,7 @@
+use crate::{apis::candles::Candle, clock};
+use std::collections::{BTreeMap, VecDeque};
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct SMA {
     pub value: Option<f64>,
     bars: usize,
@@ -26,9 +30,677 @@ impl SMA {
     }
 }
 
+// Average true range over the given number of bars.
+#[allow(clippy::upper_case_acronyms)]
+pub struct ATR {
+    pub value: Option<f64>,
+    true_ranges: SMA,
+    previous_close: Option<f64>,
+}
+
+impl ATR {
+    pub fn new(bars: usize) -> Self {
+        Self {
+            value: None,
+            true_ranges: SMA::new(bars),
+            previous_close: None,
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        let true_range = match self.previous_close {
+            Some(close) => candle.high.max(close) - candle.low.min(close),
+            None => candle.high - candle.low,
+        };
+        self.previous_close = Some(candle.close);
+        self.true_ranges.add(true_range);
+        self.value = self.true_ranges.value;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bands {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+// Bollinger bands: an SMA with bands `deviations` standard deviations away.
+pub struct Bollinger {
+    pub value: Option<Bands>,
+    bars: usize,
+    deviations: f64,
+    buffer: VecDeque<f64>,
+}
+
+impl Bollinger {
+    pub fn new(bars: usize, deviations: f64) -> Self {
+        Self {
+            value: None,
+            bars,
+            deviations,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    pub fn add(&mut self, price: f64) {
+        self.buffer.push_back(price);
+        if self.buffer.len() > self.bars {
+            self.buffer.pop_front();
+        }
+        if self.buffer.len() < self.bars {
+            return;
+        }
+
+        let middle = self.buffer.iter().sum::<f64>() / self.bars as f64;
+        let variance = self
+            .buffer
+            .iter()
+            .map(|price| (price - middle).powi(2))
+            .sum::<f64>()
+            / self.bars as f64;
+        let width = variance.sqrt() * self.deviations;
+        self.value = Some(Bands {
+            upper: middle + width,
+            middle,
+            lower: middle - width,
+        });
+    }
+}
+
+// Relative strength index with Wilder's smoothing.
+#[allow(clippy::upper_case_acronyms)]
+pub struct RSI {
+    pub value: Option<f64>,
+    bars: usize,
+    previous_price: Option<f64>,
+    changes: usize,
+    average_gain: f64,
+    average_loss: f64,
+}
+
+impl RSI {
+    pub fn new(bars: usize) -> Self {
+        Self {
+            value: None,
+            bars,
+            previous_price: None,
+            changes: 0,
+            average_gain: 0.0,
+            average_loss: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, price: f64) {
+        let previous = match self.previous_price.replace(price) {
+            Some(previous) => previous,
+            None => return,
+        };
+        let change = price - previous;
+        let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+
+        self.changes += 1;
+        let bars = self.bars as f64;
+        if self.changes <= self.bars {
+            // seed with a simple average of the first changes
+            self.average_gain += gain / bars;
+            self.average_loss += loss / bars;
+            if self.changes < self.bars {
+                return;
+            }
+        } else {
+            self.average_gain = (self.average_gain * (bars - 1.0) + gain) / bars;
+            self.average_loss = (self.average_loss * (bars - 1.0) + loss) / bars;
+        }
+
+        self.value = Some(if self.average_loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + self.average_gain / self.average_loss)
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PremarketLevels {
+    pub high: f64,
+    pub low: f64,
+    pub volume: i64,
+    pub prior_close: f64,
+    // percent from the prior close to the latest premarket price
+    pub gap_percent: f64,
+}
+
+// Premarket (4:00 - 9:30) high, low and volume along with the gap from the
+// prior regular session's close. The value keeps updating until the open and
+// is then fixed for the rest of the session.
+pub struct Premarket {
+    pub value: Option<PremarketLevels>,
+    prior_day: PriorDay,
+    session: Option<clock::LocalDate>,
+}
+
+impl Premarket {
+    pub fn new() -> Self {
+        Self {
+            value: None,
+            prior_day: PriorDay::new(),
+            session: None,
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        self.prior_day.add(candle);
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            self.session = Some(date);
+            self.value = None;
+        }
+        if !clock::is_premarket(candle.datetime) {
+            return;
+        }
+
+        // the prior day rolls over on the first regular bar, so during the
+        // premarket the last completed session is still the current one
+        let prior_close = match self.prior_day.current {
+            Some(day) => day.close,
+            None => return,
+        };
+        let gap_percent = (candle.close - prior_close) / prior_close * 100.0;
+        self.value = Some(match self.value {
+            Some(levels) => PremarketLevels {
+                high: levels.high.max(candle.high),
+                low: levels.low.min(candle.low),
+                volume: levels.volume + candle.volume,
+                prior_close,
+                gap_percent,
+            },
+            None => PremarketLevels {
+                high: candle.high,
+                low: candle.low,
+                volume: candle.volume,
+                prior_close,
+                gap_percent,
+            },
+        });
+    }
+}
+
+// Rolling OLS of y on x over `bars` pairs of prices. `value` is the z-score of
+// the latest residual y - (intercept + hedge_ratio * x) against the window's residuals.
+pub struct SpreadZScore {
+    pub value: Option<f64>,
+    pub hedge_ratio: Option<f64>,
+    bars: usize,
+    prices: VecDeque<(f64, f64)>,
+}
+
+impl SpreadZScore {
+    pub fn new(bars: usize) -> Self {
+        Self {
+            value: None,
+            hedge_ratio: None,
+            bars,
+            prices: VecDeque::new(),
+        }
+    }
+
+    pub fn add(&mut self, x: f64, y: f64) {
+        self.prices.push_back((x, y));
+        if self.prices.len() > self.bars {
+            self.prices.pop_front();
+        }
+        if self.prices.len() < self.bars {
+            return;
+        }
+
+        let n = self.bars as f64;
+        let mean_x = self.prices.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = self.prices.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance: f64 = self
+            .prices
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum();
+        let variance: f64 = self.prices.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if variance == 0.0 {
+            self.value = None;
+            self.hedge_ratio = None;
+            return;
+        }
+
+        let hedge_ratio = covariance / variance;
+        let intercept = mean_y - hedge_ratio * mean_x;
+        let residuals: Vec<f64> = self
+            .prices
+            .iter()
+            .map(|(x, y)| y - (intercept + hedge_ratio * x))
+            .collect();
+        let mean = residuals.iter().sum::<f64>() / n;
+        let deviation = (residuals.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+        self.hedge_ratio = Some(hedge_ratio);
+        self.value = if deviation > 0.0 {
+            Some((residuals[residuals.len() - 1] - mean) / deviation)
+        } else {
+            None
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValueArea {
+    pub poc: f64,
+    pub high: f64,
+    pub low: f64,
+}
+
+// Volume at price for the regular session. Each candle's volume is assigned
+// to its typical price, rounded to `tick`. `value` is the developing profile
+// for the current session and `prior` is the completed profile of the last one.
+pub struct VolumeProfile {
+    pub value: Option<ValueArea>,
+    pub prior: Option<ValueArea>,
+    tick: f64,
+    session: Option<clock::LocalDate>,
+    volume_at_price: BTreeMap<i64, i64>,
+}
+
+impl VolumeProfile {
+    const VALUE_AREA_PERCENT: f64 = 0.7;
+
+    pub fn new(tick: f64) -> Self {
+        Self {
+            value: None,
+            prior: None,
+            tick,
+            session: None,
+            volume_at_price: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        if !clock::is_regular_hours(candle.datetime) {
+            return;
+        }
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            if self.session.is_some() {
+                self.prior = self.value;
+            }
+            self.session = Some(date);
+            self.volume_at_price.clear();
+            self.value = None;
+        }
+
+        let typical_price = (candle.high + candle.low + candle.close) / 3.0;
+        let level = (typical_price / self.tick).round() as i64;
+        *self.volume_at_price.entry(level).or_insert(0) += candle.volume;
+        self.value = self.value_area();
+    }
+
+    fn value_area(&self) -> Option<ValueArea> {
+        let levels: Vec<(i64, i64)> = self.volume_at_price.iter().map(|(l, v)| (*l, *v)).collect();
+        let total: i64 = levels.iter().map(|(_, volume)| volume).sum();
+        if total == 0 {
+            return None;
+        }
+
+        // first level wins ties so the POC is stable
+        let mut poc_index = 0;
+        for (index, (_, volume)) in levels.iter().enumerate() {
+            if *volume > levels[poc_index].1 {
+                poc_index = index;
+            }
+        }
+
+        // expand from the POC toward whichever neighbor has more volume
+        let target = (total as f64 * Self::VALUE_AREA_PERCENT).ceil() as i64;
+        let (mut low, mut high) = (poc_index, poc_index);
+        let mut volume = levels[poc_index].1;
+        while volume < target {
+            let below = if low > 0 {
+                Some(levels[low - 1].1)
+            } else {
+                None
+            };
+            let above = levels.get(high + 1).map(|(_, v)| *v);
+            match (below, above) {
+                (Some(b), Some(a)) if a >= b => {
+                    high += 1;
+                    volume += a;
+                }
+                (Some(b), _) => {
+                    low -= 1;
+                    volume += b;
+                }
+                (None, Some(a)) => {
+                    high += 1;
+                    volume += a;
+                }
+                (None, None) => break,
+            }
+        }
+
+        Some(ValueArea {
+            poc: levels[poc_index].0 as f64 * self.tick,
+            high: levels[high].0 as f64 * self.tick,
+            low: levels[low].0 as f64 * self.tick,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DayLevels {
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+// High, low and close of the previous regular session.
+pub struct PriorDay {
+    pub value: Option<DayLevels>,
+    session: Option<clock::LocalDate>,
+    current: Option<DayLevels>,
+}
+
+impl PriorDay {
+    pub fn new() -> Self {
+        Self {
+            value: None,
+            session: None,
+            current: None,
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        if !clock::is_regular_hours(candle.datetime) {
+            return;
+        }
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            if self.current.is_some() {
+                self.value = self.current;
+            }
+            self.session = Some(date);
+            self.current = None;
+        }
+
+        self.current = Some(match self.current {
+            Some(day) => DayLevels {
+                high: day.high.max(candle.high),
+                low: day.low.min(candle.low),
+                close: candle.close,
+            },
+            None => DayLevels {
+                high: candle.high,
+                low: candle.low,
+                close: candle.close,
+            },
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub high: f64,
+    pub low: f64,
+}
+
+impl Range {
+    pub fn midpoint(&self) -> f64 {
+        (self.high + self.low) / 2.0
+    }
+
+    pub fn size(&self) -> f64 {
+        self.high - self.low
+    }
+}
+
+// High and low of the first `minutes` of the regular session. The value is
+// only set once the range is complete and is cleared at the next session.
+pub struct OpeningRange {
+    pub value: Option<Range>,
+    minutes: i64,
+    session: Option<clock::LocalDate>,
+    forming: Option<Range>,
+}
+
+impl OpeningRange {
+    pub fn new(minutes: i64) -> Self {
+        Self {
+            value: None,
+            minutes,
+            session: None,
+            forming: None,
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        if !clock::is_regular_hours(candle.datetime) {
+            return;
+        }
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            self.session = Some(date);
+            self.value = None;
+            self.forming = None;
+        }
+        if self.value.is_some() {
+            return;
+        }
+
+        let range_end = date.and_hms(9, 30, 0) + clock::Duration::minutes(self.minutes);
+        if candle.datetime >= range_end {
+            self.value = self.forming;
+            return;
+        }
+
+        let range = match self.forming {
+            Some(range) => Range {
+                high: range.high.max(candle.high),
+                low: range.low.min(candle.low),
+            },
+            None => Range {
+                high: candle.high,
+                low: candle.low,
+            },
+        };
+        self.forming = Some(range);
+
+        // the candle covering the last minute of the range completes it
+        if candle.datetime + clock::Duration::minutes(1) >= range_end {
+            self.value = self.forming;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pivots {
+    pub pivot: f64,
+    pub r1: f64,
+    pub r2: f64,
+    pub r3: f64,
+    pub s1: f64,
+    pub s2: f64,
+    pub s3: f64,
+}
+
+// Floor trader pivot points from the prior day's high, low and close.
+pub struct PivotPoints {
+    pub value: Option<Pivots>,
+    prior_day: PriorDay,
+}
+
+impl PivotPoints {
+    pub fn new() -> Self {
+        Self {
+            value: None,
+            prior_day: PriorDay::new(),
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        self.prior_day.add(candle);
+        self.value = self.prior_day.value.map(|day| {
+            let pivot = (day.high + day.low + day.close) / 3.0;
+            let range = day.high - day.low;
+            Pivots {
+                pivot,
+                r1: 2.0 * pivot - day.low,
+                r2: pivot + range,
+                r3: day.high + 2.0 * (pivot - day.low),
+                s1: 2.0 * pivot - day.high,
+                s2: pivot - range,
+                s3: day.low - 2.0 * (day.high - pivot),
+            }
+        });
+    }
+}
+
+// Volume of the current bar compared to the average volume of the bar at the
+// same time of day over the previous `sessions` regular sessions.
+pub struct RelativeVolume {
+    pub value: Option<f64>,
+    sessions: usize,
+    session: Option<clock::LocalDate>,
+    current: BTreeMap<u32, i64>,
+    history: VecDeque<BTreeMap<u32, i64>>,
+}
+
+impl RelativeVolume {
+    pub fn new(sessions: usize) -> Self {
+        Self {
+            value: None,
+            sessions,
+            session: None,
+            current: BTreeMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        if !clock::is_regular_hours(candle.datetime) {
+            return;
+        }
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            if self.session.is_some() {
+                let finished = std::mem::take(&mut self.current);
+                self.history.push_back(finished);
+                if self.history.len() > self.sessions {
+                    self.history.pop_front();
+                }
+            }
+            self.session = Some(date);
+        }
+
+        let minute = clock::minute_of_day(candle.datetime);
+        self.current.insert(minute, candle.volume);
+        self.value = None;
+        if self.history.len() < self.sessions {
+            return;
+        }
+
+        // sessions missing this bar count as zero volume
+        let total: i64 = self
+            .history
+            .iter()
+            .map(|session| session.get(&minute).copied().unwrap_or(0))
+            .sum();
+        let average = total as f64 / self.sessions as f64;
+        if average > 0.0 {
+            self.value = Some(candle.volume as f64 / average);
+        }
+    }
+}
+
+// Cumulative volume for the current regular session as a fraction of the
+// average daily volume over the previous `sessions` regular sessions.
+pub struct IntradayVolume {
+    pub value: Option<f64>,
+    pub cumulative: i64,
+    session: Option<clock::LocalDate>,
+    daily_volume: SMA,
+}
+
+impl IntradayVolume {
+    pub fn new(sessions: usize) -> Self {
+        Self {
+            value: None,
+            cumulative: 0,
+            session: None,
+            daily_volume: SMA::new(sessions),
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        if !clock::is_regular_hours(candle.datetime) {
+            return;
+        }
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            if self.session.is_some() {
+                self.daily_volume.add(self.cumulative as f64);
+            }
+            self.session = Some(date);
+            self.cumulative = 0;
+        }
+
+        self.cumulative += candle.volume;
+        self.value = match self.daily_volume.value {
+            Some(average) if average > 0.0 => Some(self.cumulative as f64 / average),
+            _ => None,
+        };
+    }
+}
+
+// Percent gap between the regular session's open and the prior day's close.
+pub struct GapPercent {
+    pub value: Option<f64>,
+    prior_day: PriorDay,
+    session: Option<clock::LocalDate>,
+}
+
+impl GapPercent {
+    pub fn new() -> Self {
+        Self {
+            value: None,
+            prior_day: PriorDay::new(),
+            session: None,
+        }
+    }
+
+    pub fn add(&mut self, candle: &Candle) {
+        self.prior_day.add(candle);
+        if !clock::is_regular_hours(candle.datetime) {
+            return;
+        }
+
+        let date = candle.datetime.date();
+        if self.session != Some(date) {
+            self.session = Some(date);
+            self.value = self
+                .prior_day
+                .value
+                .map(|day| (candle.open - day.close) / day.close * 100.0);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::SMA;
+    use super::{
+        Bollinger, GapPercent, IntradayVolume, OpeningRange, PivotPoints, Premarket, PriorDay,
+        Range, RelativeVolume, SpreadZScore, VolumeProfile, ATR, RSI, SMA,
+    };
+    use crate::{apis::candles::Candle, clock};
 
     #[test]
     fn sma_adding_prices_below_bar_length_does_not_calculate_value() {
@@ -58,6 +730,419 @@ mod tests {
         assert_eq!(sma.value.unwrap(), 29.26);
     }
 
+    #[test]
+    fn atr_uses_previous_close_for_gaps() {
+        let time = clock::datetime(2020, 9, 29, 9, 30, 0);
+        let mut atr = ATR::new(2);
+        atr.add(&Candle::new(10.0, 11.0, 12.0, 9.0, 100, time));
+        assert_eq!(atr.value, None);
+        atr.add(&Candle::new(14.0, 15.0, 16.0, 14.0, 100, time));
+        assert_eq!(atr.value.unwrap(), 4.0);
+    }
+
+    #[test]
+    fn volume_profile_expands_value_area_toward_heavier_side() {
+        let mut profile = VolumeProfile::new(1.0);
+        let time = clock::datetime(2020, 9, 29, 10, 0, 0);
+        profile.add(&Candle::new(10.0, 10.0, 10.0, 10.0, 100, time));
+        profile.add(&Candle::new(11.0, 11.0, 11.0, 11.0, 500, time));
+        profile.add(&Candle::new(12.0, 12.0, 12.0, 12.0, 300, time));
+        profile.add(&Candle::new(13.0, 13.0, 13.0, 13.0, 100, time));
+
+        let value_area = profile.value.unwrap();
+        assert_eq!(value_area.poc, 11.0);
+        assert_eq!(value_area.high, 12.0);
+        assert_eq!(value_area.low, 11.0);
+    }
+
+    #[test]
+    fn volume_profile_keeps_prior_session_and_ignores_extended_hours() {
+        let mut profile = VolumeProfile::new(0.5);
+        profile.add(&Candle::new(
+            10.0,
+            10.0,
+            10.0,
+            10.0,
+            100,
+            clock::datetime(2020, 9, 28, 10, 0, 0),
+        ));
+        profile.add(&Candle::new(
+            20.0,
+            20.0,
+            20.0,
+            20.0,
+            900,
+            clock::datetime(2020, 9, 28, 17, 0, 0),
+        ));
+        profile.add(&Candle::new(
+            12.0,
+            12.0,
+            12.0,
+            12.0,
+            100,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        ));
+
+        assert_eq!(profile.prior.unwrap().poc, 10.0);
+        assert_eq!(profile.value.unwrap().poc, 12.0);
+    }
+
+    #[test]
+    fn prior_day_levels_come_from_the_previous_regular_session() {
+        let mut prior_day = PriorDay::new();
+        prior_day.add(&Candle::new(
+            10.0,
+            10.0,
+            11.0,
+            9.0,
+            100,
+            clock::datetime(2020, 9, 28, 9, 30, 0),
+        ));
+        prior_day.add(&Candle::new(
+            11.5,
+            11.5,
+            12.0,
+            10.0,
+            100,
+            clock::datetime(2020, 9, 28, 15, 59, 0),
+        ));
+        prior_day.add(&Candle::new(
+            13.0,
+            13.0,
+            13.0,
+            13.0,
+            100,
+            clock::datetime(2020, 9, 28, 18, 0, 0),
+        ));
+        assert_eq!(prior_day.value, None);
+
+        prior_day.add(&Candle::new(
+            14.0,
+            14.0,
+            14.0,
+            14.0,
+            100,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        ));
+        let levels = prior_day.value.unwrap();
+        assert_eq!(levels.high, 12.0);
+        assert_eq!(levels.low, 9.0);
+        assert_eq!(levels.close, 11.5);
+    }
+
+    #[test]
+    fn opening_range_is_set_after_the_range_minutes() {
+        let mut opening_range = OpeningRange::new(2);
+        opening_range.add(&Candle::new(
+            10.5,
+            10.5,
+            11.0,
+            10.0,
+            100,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        ));
+        assert_eq!(opening_range.value, None);
+        opening_range.add(&Candle::new(
+            11.0,
+            11.0,
+            12.0,
+            9.5,
+            100,
+            clock::datetime(2020, 9, 29, 9, 31, 0),
+        ));
+        assert_eq!(
+            opening_range.value,
+            Some(Range {
+                high: 12.0,
+                low: 9.5
+            })
+        );
+
+        opening_range.add(&Candle::new(
+            11.0,
+            11.0,
+            20.0,
+            1.0,
+            100,
+            clock::datetime(2020, 9, 29, 9, 32, 0),
+        ));
+        assert_eq!(opening_range.value.unwrap().high, 12.0);
+
+        opening_range.add(&Candle::new(
+            11.0,
+            11.0,
+            20.0,
+            1.0,
+            100,
+            clock::datetime(2020, 9, 30, 9, 30, 0),
+        ));
+        assert_eq!(opening_range.value, None);
+    }
+
+    #[test]
+    fn pivot_points_use_prior_day_levels() {
+        let mut pivots = PivotPoints::new();
+        pivots.add(&Candle::new(
+            10.5,
+            10.5,
+            12.0,
+            9.0,
+            100,
+            clock::datetime(2020, 9, 28, 10, 0, 0),
+        ));
+        assert_eq!(pivots.value, None);
+        pivots.add(&Candle::new(
+            11.0,
+            11.0,
+            11.0,
+            11.0,
+            100,
+            clock::datetime(2020, 9, 29, 10, 0, 0),
+        ));
+
+        let levels = pivots.value.unwrap();
+        assert_eq!(levels.pivot, 10.5);
+        assert_eq!(levels.r1, 12.0);
+        assert_eq!(levels.s1, 9.0);
+        assert_eq!(levels.r2, 13.5);
+        assert_eq!(levels.s2, 7.5);
+        assert_eq!(levels.r3, 15.0);
+        assert_eq!(levels.s3, 6.0);
+    }
+
+    #[test]
+    fn relative_volume_compares_the_same_time_of_day() {
+        let mut rvol = RelativeVolume::new(2);
+        rvol.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            100,
+            clock::datetime(2020, 9, 25, 9, 30, 0),
+        ));
+        rvol.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            900,
+            clock::datetime(2020, 9, 25, 9, 31, 0),
+        ));
+        rvol.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            300,
+            clock::datetime(2020, 9, 28, 9, 30, 0),
+        ));
+        assert_eq!(rvol.value, None);
+
+        rvol.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            400,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        ));
+        assert_eq!(rvol.value.unwrap(), 2.0);
+        rvol.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            900,
+            clock::datetime(2020, 9, 29, 9, 31, 0),
+        ));
+        assert_eq!(rvol.value.unwrap(), 2.0);
+    }
+
+    #[test]
+    fn intraday_volume_is_relative_to_average_daily_volume() {
+        let mut volume = IntradayVolume::new(2);
+        volume.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            1000,
+            clock::datetime(2020, 9, 25, 10, 0, 0),
+        ));
+        volume.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            3000,
+            clock::datetime(2020, 9, 28, 10, 0, 0),
+        ));
+        volume.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            500,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        ));
+        assert_eq!(volume.value.unwrap(), 0.25);
+        volume.add(&Candle::new(
+            1.0,
+            1.0,
+            1.0,
+            1.0,
+            500,
+            clock::datetime(2020, 9, 29, 9, 31, 0),
+        ));
+        assert_eq!(volume.value.unwrap(), 0.5);
+        assert_eq!(volume.cumulative, 1000);
+    }
+
+    #[test]
+    fn gap_percent_measures_open_against_prior_close() {
+        let mut gap = GapPercent::new();
+        gap.add(&Candle::new(
+            10.0,
+            10.0,
+            10.0,
+            10.0,
+            100,
+            clock::datetime(2020, 9, 28, 15, 59, 0),
+        ));
+        assert_eq!(gap.value, None);
+
+        let open = Candle::new(
+            11.0,
+            12.0,
+            12.0,
+            11.0,
+            100,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        );
+        gap.add(&open);
+        assert_eq!(gap.value.unwrap(), 10.0);
+        gap.add(&Candle::new(
+            15.0,
+            15.0,
+            15.0,
+            15.0,
+            100,
+            clock::datetime(2020, 9, 29, 9, 31, 0),
+        ));
+        assert_eq!(gap.value.unwrap(), 10.0);
+    }
+
+    #[test]
+    fn bollinger_bands_are_standard_deviations_from_the_average() {
+        let mut bollinger = Bollinger::new(4, 2.0);
+        for price in &[2.0, 4.0, 4.0] {
+            bollinger.add(*price);
+        }
+        assert_eq!(bollinger.value, None);
+
+        bollinger.add(6.0);
+        let bands = bollinger.value.unwrap();
+        assert_eq!(bands.middle, 4.0);
+        assert!((bands.upper - (4.0 + 2.0 * 2f64.sqrt())).abs() < 1e-9);
+        assert!((bands.lower - (4.0 - 2.0 * 2f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rsi_is_calculated_after_the_first_bars_changes() {
+        let mut rsi = RSI::new(2);
+        rsi.add(10.0);
+        rsi.add(11.0);
+        assert_eq!(rsi.value, None);
+        rsi.add(10.0);
+        assert_eq!(rsi.value.unwrap(), 50.0);
+
+        // a gain of 1.0 smooths to avg gain 0.75 and avg loss 0.25
+        rsi.add(11.0);
+        assert_eq!(rsi.value.unwrap(), 75.0);
+    }
+
+    #[test]
+    fn rsi_is_100_without_losses() {
+        let mut rsi = RSI::new(2);
+        for price in &[1.0, 2.0, 3.0] {
+            rsi.add(*price);
+        }
+        assert_eq!(rsi.value.unwrap(), 100.0);
+    }
+
+    #[test]
+    fn spread_z_score_fits_the_hedge_ratio() {
+        let mut spread = SpreadZScore::new(4);
+        spread.add(1.0, 2.0);
+        spread.add(2.0, 4.0);
+        spread.add(3.0, 6.0);
+        assert_eq!(spread.value, None);
+
+        spread.add(4.0, 8.0);
+        assert_eq!(spread.hedge_ratio.unwrap(), 2.0);
+        assert_eq!(spread.value, None);
+
+        // y jumps above the fitted line, so the latest residual is rich
+        spread.add(5.0, 12.0);
+        assert!(spread.value.unwrap() > 1.0);
+    }
+
+    #[test]
+    fn premarket_levels_measure_the_gap_from_prior_close() {
+        let mut premarket = Premarket::new();
+        premarket.add(&Candle::new(
+            10.0,
+            10.0,
+            10.0,
+            10.0,
+            100,
+            clock::datetime(2020, 9, 28, 15, 59, 0),
+        ));
+        premarket.add(&Candle::new(
+            11.0,
+            11.0,
+            11.0,
+            11.0,
+            100,
+            clock::datetime(2020, 9, 28, 17, 0, 0),
+        ));
+        assert_eq!(premarket.value, None);
+
+        premarket.add(&Candle::new(
+            11.0,
+            11.0,
+            11.5,
+            10.5,
+            200,
+            clock::datetime(2020, 9, 29, 4, 36, 0),
+        ));
+        premarket.add(&Candle::new(
+            12.0,
+            12.0,
+            12.5,
+            11.0,
+            300,
+            clock::datetime(2020, 9, 29, 8, 0, 0),
+        ));
+        let levels = premarket.value.unwrap();
+        assert_eq!(levels.high, 12.5);
+        assert_eq!(levels.low, 10.5);
+        assert_eq!(levels.volume, 500);
+        assert_eq!(levels.prior_close, 10.0);
+        assert_eq!(levels.gap_percent, 20.0);
+
+        premarket.add(&Candle::new(
+            20.0,
+            20.0,
+            20.0,
+            20.0,
+            900,
+            clock::datetime(2020, 9, 29, 9, 30, 0),
+        ));
+        assert_eq!(premarket.value, Some(levels));
+    }
+
     //     #[test]
     //     fn sma_averages_starting_from_the_end_of_the_vector() {
     //         let prices = PRICES.to_vec();
@@ -0,0 +1,168 @@
+use super::{apis::candles::Candle, clock};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timeframe {
+    Minutes(u32),
+    Daily,
+}
+
+impl Timeframe {
+    // Start of the bucket a candle's datetime falls into. Minute buckets are
+    // aligned to midnight so 15 minute bars start at :00, :15, :30 and :45.
+    fn bucket_start(&self, datetime: clock::LocalDateTime) -> clock::LocalDateTime {
+        let midnight = datetime.date().and_hms(0, 0, 0);
+        match self {
+            Timeframe::Minutes(minutes) => {
+                let elapsed = (datetime - midnight).num_minutes();
+                let size = *minutes as i64;
+                midnight + clock::Duration::minutes(elapsed / size * size)
+            }
+            Timeframe::Daily => midnight,
+        }
+    }
+
+    // The minute bar at `datetime` is the last one in its bucket.
+    fn closes_bucket(&self, datetime: clock::LocalDateTime) -> bool {
+        match self {
+            Timeframe::Minutes(minutes) => {
+                let bucket_end =
+                    self.bucket_start(datetime) + clock::Duration::minutes(*minutes as i64);
+                datetime + clock::Duration::minutes(1) >= bucket_end
+            }
+            // Extended hours make the last bar of the day unknowable, so a
+            // daily bar is only complete once the next day's first bar arrives.
+            Timeframe::Daily => false,
+        }
+    }
+}
+
+// Builds higher timeframe bars from a stream of one minute candles.
+// Only completed bars are exposed, so a strategy can never see the
+// high/low/close of a bar that is still forming.
+pub struct Bars {
+    pub timeframe: Timeframe,
+    pub completed: Vec<Candle>,
+    forming: Option<Candle>,
+}
+
+impl Bars {
+    pub fn new(timeframe: Timeframe) -> Self {
+        Self {
+            timeframe,
+            completed: Vec::new(),
+            forming: None,
+        }
+    }
+
+    // Returns the bars completed by this candle, usually none or one.
+    pub fn add(&mut self, candle: &Candle) -> &[Candle] {
+        let already_completed = self.completed.len();
+        let bucket = self.timeframe.bucket_start(candle.datetime);
+
+        match self.forming.take() {
+            Some(mut bar) if bar.datetime == bucket => {
+                bar.high = bar.high.max(candle.high);
+                bar.low = bar.low.min(candle.low);
+                bar.close = candle.close;
+                bar.volume += candle.volume;
+                self.forming = Some(bar);
+            }
+            previous => {
+                if let Some(bar) = previous {
+                    self.completed.push(bar);
+                }
+                let mut bar = candle.clone();
+                bar.datetime = bucket;
+                self.forming = Some(bar);
+            }
+        }
+
+        if self.timeframe.closes_bucket(candle.datetime) {
+            if let Some(bar) = self.forming.take() {
+                self.completed.push(bar);
+            }
+        }
+        &self.completed[already_completed..]
+    }
+
+    // Completes the forming bar, for when the stream is known to have ended.
+    pub fn flush(&mut self) -> &[Candle] {
+        let already_completed = self.completed.len();
+        if let Some(bar) = self.forming.take() {
+            self.completed.push(bar);
+        }
+        &self.completed[already_completed..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bars, Timeframe};
+    use crate::{
+        apis::candles::{candle, Candle},
+        clock,
+    };
+
+    #[test]
+    fn bars_are_not_exposed_until_complete() {
+        let mut bars = Bars::new(Timeframe::Minutes(5));
+        for minute in 0..4 {
+            assert!(bars
+                .add(&candle(9.5, 10.0, 11.0, 9.0, 100, minute))
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn last_minute_in_bucket_completes_the_bar() {
+        let mut bars = Bars::new(Timeframe::Minutes(5));
+        bars.add(&candle(9.5, 10.0, 11.0, 9.0, 100, 0));
+        bars.add(&candle(11.5, 12.0, 13.0, 11.0, 100, 1));
+        bars.add(&candle(8.5, 9.0, 10.0, 8.0, 100, 2));
+        bars.add(&candle(10.0, 10.5, 11.5, 9.5, 100, 3));
+        let completed = bars.add(&candle(10.5, 11.0, 12.0, 10.0, 100, 4));
+
+        assert_eq!(completed.len(), 1);
+        let bar = &completed[0];
+        assert_eq!(bar.open, 9.5);
+        assert_eq!(bar.close, 11.0);
+        assert_eq!(bar.high, 13.0);
+        assert_eq!(bar.low, 8.0);
+        assert_eq!(bar.volume, 500);
+        assert_eq!(bar.datetime, clock::datetime(2020, 9, 29, 9, 30, 0));
+    }
+
+    #[test]
+    fn gap_in_data_completes_the_forming_bar() {
+        let mut bars = Bars::new(Timeframe::Minutes(15));
+        bars.add(&candle(9.5, 10.0, 11.0, 9.0, 100, 0));
+        bars.add(&candle(10.5, 11.0, 12.0, 10.0, 100, 1));
+        let completed = bars.add(&candle(11.5, 12.0, 13.0, 11.0, 100, 17));
+
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].close, 11.0);
+        assert_eq!(completed[0].volume, 200);
+    }
+
+    #[test]
+    fn daily_bars_complete_on_the_next_session() {
+        let mut bars = Bars::new(Timeframe::Daily);
+        assert!(bars.add(&candle(9.5, 10.0, 11.0, 9.0, 100, 0)).is_empty());
+        assert!(bars
+            .add(&candle(10.5, 11.0, 12.0, 10.0, 100, 389))
+            .is_empty());
+
+        let next_day = Candle::new(
+            12.0,
+            12.0,
+            12.0,
+            12.0,
+            100,
+            clock::datetime(2020, 9, 30, 9, 30, 0),
+        );
+        let completed = bars.add(&next_day);
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].close, 11.0);
+        assert_eq!(completed[0].high, 12.0);
+    }
+}
@@ -1,37 +1,89 @@
-use super::{apis, apis::candles::Candle, clock};
+use super::{
+    apis,
+    apis::candles::Candle,
+    clock,
+    engine::{FillAt, Signal},
+    exits::Exits,
+    pdt::DayTrades,
+    risk::{Order, RiskManager},
+    sizing::SizingPolicy,
+    studies,
+    timeframes::{Bars, Timeframe},
+};
 use colored::*;
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 pub struct PriceData<'a> {
-    client: apis::alpha_vantage::Client<'a>,
+    client: Option<apis::alpha_vantage::Client<'a>>,
     candles: Vec<Candle>,
     current_index: usize,
+    // candles before this were history
+    start_index: usize,
 }
 
 impl<'a> PriceData<'a> {
     pub fn new(client: apis::alpha_vantage::Client<'a>) -> Self {
         Self {
-            client,
+            client: Some(client),
             candles: Vec::new(),
             current_index: 0,
+            start_index: 0,
+        }
+    }
+
+    // Price data over candles that are already loaded, e.g. test fixtures.
+    pub fn with_candles(candles: Vec<Candle>) -> Self {
+        Self {
+            client: None,
+            candles,
+            current_index: 0,
+            start_index: 0,
         }
     }
 
     pub fn history(&mut self, ticker: &String, bars: usize, frequency: &str) -> Option<&[Candle]> {
-        let (frequency, frequency_type) = parse_frequency(frequency);
-        let start_date = clock::days_ago(70);
-        let end_date = clock::current_date();
-        let history =
-            self.client
-                .price_history(ticker, start_date, end_date, frequency, frequency_type);
-
-        if let Some(candles) = history {
-            self.candles = candles;
-            self.current_index = bars;
-            Some(&self.candles[..bars])
-        } else {
-            None
+        if let Some(client) = &self.client {
+            self.candles = fetch_candles(client, ticker, frequency)?;
+        }
+
+        if self.candles.len() < bars {
+            return None;
         }
+        self.current_index = bars;
+        self.start_index = bars;
+        Some(&self.candles[..bars])
+    }
+
+    // Like history but over daily bars of the regular session, stamped with the
+    // session's last minute so daily decisions are made and filled at the close.
+    pub fn daily_history(&mut self, ticker: &String, bars: usize) -> Option<&[Candle]> {
+        if let Some(client) = &self.client {
+            self.candles = fetch_candles(client, ticker, "1:minute")?;
+        }
+        self.candles = daily_bars(&self.candles);
+
+        if self.candles.len() < bars {
+            return None;
+        }
+        self.current_index = bars;
+        self.start_index = bars;
+        Some(&self.candles[..bars])
+    }
+
+    // Candles after the history, which the strategy trades over. Only for
+    // after the run, since during it they include candles yet to be seen.
+    pub fn traded_candles(&self) -> &[Candle] {
+        let candles = &self.candles[self.start_index..];
+        if let Some(last) = candles.last() {
+            guard_look_ahead(self.now(), last.datetime);
+        }
+        candles
+    }
+
+    // The time of the last candle handed out, history included.
+    pub fn now(&self) -> Option<clock::LocalDateTime> {
+        let seen = self.current_index.min(self.candles.len());
+        self.candles.get(seen.checked_sub(1)?).map(|c| c.datetime)
     }
 
     pub fn next_candle(&mut self) -> Option<&Candle> {
@@ -41,23 +93,250 @@ impl<'a> PriceData<'a> {
     }
 }
 
+// Reading data stamped after the simulation's current time lets a strategy see
+// the future. Debug builds, tests included, panic when it happens.
+pub fn guard_look_ahead(now: Option<clock::LocalDateTime>, time: clock::LocalDateTime) {
+    debug_assert!(
+        now.is_some_and(|now| time <= now),
+        "look-ahead: read data at {} before the simulation reached it",
+        time
+    );
+}
+
+fn daily_bars(candles: &[Candle]) -> Vec<Candle> {
+    let mut bars = Bars::new(Timeframe::Daily);
+    for candle in candles {
+        if clock::is_regular_hours(candle.datetime) {
+            bars.add(candle);
+        }
+    }
+    bars.flush();
+
+    bars.completed
+        .into_iter()
+        .map(|mut bar| {
+            bar.datetime = bar.datetime.date().and_hms(15, 59, 0);
+            bar
+        })
+        .collect()
+}
+
+// Calendar days of price history fetch_candles requests.
+const HISTORY_DAYS: i64 = 70;
+
+// Daily bars in that history: its weekdays, less a couple of market holidays.
+pub const HISTORY_DAILY_BARS: usize = HISTORY_DAYS as usize * 5 / 7 - 2;
+
+pub fn fetch_candles(
+    client: &apis::alpha_vantage::Client,
+    ticker: &String,
+    frequency: &str,
+) -> Option<Vec<Candle>> {
+    let (frequency, frequency_type) = parse_frequency(frequency);
+    let start_date = clock::days_ago(HISTORY_DAYS);
+    let end_date = clock::current_date();
+    client.price_history(ticker, start_date, end_date, frequency, frequency_type)
+}
+
+// Keeps only the timestamps both series have candles for, in order.
+pub fn align_candles(a: Vec<Candle>, b: Vec<Candle>) -> Vec<(Candle, Candle)> {
+    let mut pairs = Vec::new();
+    let mut b = b.into_iter().peekable();
+    for candle_a in a {
+        while let Some(candle_b) = b.peek() {
+            if candle_b.datetime < candle_a.datetime {
+                b.next();
+            } else {
+                break;
+            }
+        }
+        if let Some(candle_b) = b.peek() {
+            if candle_b.datetime == candle_a.datetime {
+                pairs.push((candle_a, b.next().unwrap()));
+            }
+        }
+    }
+    pairs
+}
+
+// Price data for two tickers traded together, aligned by timestamp.
+pub struct PairPriceData<'a> {
+    client: Option<apis::alpha_vantage::Client<'a>>,
+    pairs: Vec<(Candle, Candle)>,
+    current_index: usize,
+}
+
+impl<'a> PairPriceData<'a> {
+    pub fn new(client: apis::alpha_vantage::Client<'a>) -> Self {
+        Self {
+            client: Some(client),
+            pairs: Vec::new(),
+            current_index: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_candles(a: Vec<Candle>, b: Vec<Candle>) -> Self {
+        Self {
+            client: None,
+            pairs: align_candles(a, b),
+            current_index: 0,
+        }
+    }
+
+    pub fn history(
+        &mut self,
+        tickers: (&String, &String),
+        bars: usize,
+        frequency: &str,
+    ) -> Option<&[(Candle, Candle)]> {
+        if let Some(client) = &self.client {
+            let a = fetch_candles(client, tickers.0, frequency)?;
+            let b = fetch_candles(client, tickers.1, frequency)?;
+            self.pairs = align_candles(a, b);
+        }
+
+        if self.pairs.len() < bars {
+            return None;
+        }
+        self.current_index = bars;
+        Some(&self.pairs[..bars])
+    }
+
+    pub fn next_pair(&mut self) -> Option<&(Candle, Candle)> {
+        let pair = self.pairs.get(self.current_index);
+        self.current_index += 1;
+        pair
+    }
+}
+
 pub trait Broker {
     fn capital(&mut self, time: clock::LocalDateTime) -> f64;
     fn unsettled_cash(&self) -> f64;
     fn is_market_open(&self, datetime: clock::LocalDateTime) -> bool;
-    fn sell_order(&mut self, _ticker: &String, shares: i32, price: f64, time: clock::LocalDateTime);
+
+    // Cash plus the value of open positions. Brokers that don't take the cost
+    // of open positions out of their capital should report the capital alone.
+    fn equity(&mut self, time: clock::LocalDateTime, open_value: f64) -> f64 {
+        self.unsettled_cash() + self.capital(time) + open_value
+    }
+    fn sell_order(&mut self, _ticker: &str, shares: i32, price: f64, time: clock::LocalDateTime);
     fn buy_order(
         &mut self,
-        _ticker: &String,
+        _ticker: &str,
         shares: i32,
         price: f64,
         _time: clock::LocalDateTime,
     ) -> Option<f64>;
+
+    // Brokers that can't lend shares, like a cash account, reject short sales.
+    fn short_order(
+        &mut self,
+        _ticker: &str,
+        _shares: i32,
+        _price: f64,
+        _time: clock::LocalDateTime,
+    ) -> Option<f64> {
+        None
+    }
+
+    fn cover_order(
+        &mut self,
+        _ticker: &str,
+        _shares: i32,
+        _price: f64,
+        _time: clock::LocalDateTime,
+    ) {
+    }
+
+    // The latest price for a ticker, for brokers that value positions themselves.
+    fn mark(&mut self, _ticker: &str, _price: f64, _time: clock::LocalDateTime) {}
+
+    // True when the account is below its maintenance requirement and the
+    // broker would liquidate it.
+    fn margin_call(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Holding {
+    // flat by the end of every day
+    #[default]
+    Intraday,
+    // held across sessions, usually on daily bars
+    Swing(SwingConfig),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwingConfig {
+    // business days a position may be held before it's closed
+    pub max_holding_days: i64,
+    // stop distance from the entry price, filled at the open when gapped through
+    pub stop_percent: Option<f64>,
+    // an overnight gap larger than this is taken for a split or other corporate
+    // action the price data isn't adjusted for, rather than a real move
+    pub max_gap_percent: f64,
+}
+
+impl Default for SwingConfig {
+    fn default() -> Self {
+        Self {
+            max_holding_days: 10,
+            stop_percent: Some(5.0),
+            max_gap_percent: 40.0,
+        }
+    }
+}
+
+// An overnight gap on a held swing position too large to be a real move.
+pub struct SuspectGap {
+    pub ticker: String,
+    pub time: clock::LocalDateTime,
+    pub percent: f64,
+    // the close before the gap, where the position was closed
+    pub close: f64,
+}
+
+impl fmt::Display for SuspectGap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} - {:+.2}% overnight gap looks like a split, closed at the prior close ${:.2}",
+            self.ticker, self.time, self.percent, self.close
+        )
+    }
+}
+
+const MAX_RECENT_CANDLES: usize = 500;
+
+// An order sent with FillAt::NextOpen, waiting for the ticker's next candle.
+struct PendingOrder<'a> {
+    ticker: &'a String,
+    signal: Signal,
+    shares: i32,
+    sent: clock::LocalDateTime,
 }
 
 pub struct Account<'a, B> {
     pub positions: Vec<Position<'a>>,
     pub broker: B,
+    pub holding: Holding,
+    pub exits: Exits,
+    pub sizing: SizingPolicy,
+    pub risk: RiskManager,
+    pub day_trades: DayTrades,
+    // times the broker called for margin and the account was liquidated
+    pub margin_calls: Vec<clock::LocalDateTime>,
+    pub suspect_gaps: Vec<SuspectGap>,
+    // how orders from send_order fill; the account's own closes, e.g. for
+    // exits or the end of day, always fill right away
+    pub fill_at: FillAt,
+    pending: Vec<PendingOrder<'a>>,
+    candles: HashMap<String, Vec<Candle>>,
+    last_closes: HashMap<String, (clock::LocalDateTime, f64)>,
+    // the latest time the account has reached, None until it sees a candle
+    now: Option<clock::LocalDateTime>,
 }
 
 impl<'a, B> Account<'a, B>
@@ -68,6 +347,18 @@ where
         Self {
             broker,
             positions: Vec::new(),
+            holding: Holding::Intraday,
+            exits: Exits::default(),
+            sizing: SizingPolicy::default(),
+            risk: RiskManager::default(),
+            day_trades: DayTrades::default(),
+            margin_calls: Vec::new(),
+            suspect_gaps: Vec::new(),
+            fill_at: FillAt::Close,
+            pending: Vec::new(),
+            candles: HashMap::new(),
+            last_closes: HashMap::new(),
+            now: None,
         }
     }
 
@@ -79,6 +370,32 @@ where
         (self.broker.capital(time) / price) as i32
     }
 
+    // Open positions are valued at their entry price.
+    pub fn equity(&mut self, time: clock::LocalDateTime) -> f64 {
+        let open_value = self
+            .positions
+            .iter()
+            .filter(|position| position.open && !position.is_short())
+            .map(|position| position.shares as f64 * position.bid)
+            .sum();
+        self.broker.equity(time, open_value)
+    }
+
+    // Shares for a new position in ticker under the account's sizing policy.
+    pub fn position_size(&mut self, ticker: &str, price: f64, time: clock::LocalDateTime) -> i32 {
+        self.sizing.clone().shares(self, ticker, price, time)
+    }
+
+    // ATR from the candles passed to add_candle, None until there are enough.
+    pub fn atr(&self, ticker: &str, bars: usize) -> Option<f64> {
+        let candles = self.candles.get(ticker)?;
+        let mut atr = studies::ATR::new(bars);
+        for candle in &candles[candles.len().saturating_sub(bars + 1)..] {
+            atr.add(candle);
+        }
+        atr.value
+    }
+
     pub fn open_position(
         &mut self,
         ticker: &'a String,
@@ -86,25 +403,226 @@ where
         shares: i32,
         time: clock::LocalDateTime,
     ) {
+        self.guard_order(time);
         if shares <= 0 || !self.broker.is_market_open(time) {
             return;
         }
+        if !self.approve(ticker, shares, bid, time) {
+            return;
+        }
 
-        if let Some(_) = self.broker.buy_order(ticker, shares, bid, time) {
+        if self.broker.buy_order(ticker, shares, bid, time).is_some() {
+            self.risk.record_order(time);
             let pos = Position::open(ticker, shares, bid, time);
             self.positions.push(pos);
+            self.exits.open(ticker, bid, false);
         }
     }
 
-    pub fn current_position(&self) -> Option<&Position> {
+    // Short positions are recorded with negative shares.
+    pub fn open_short_position(
+        &mut self,
+        ticker: &'a String,
+        ask: f64,
+        shares: i32,
+        time: clock::LocalDateTime,
+    ) {
+        self.guard_order(time);
+        if shares <= 0 || !self.broker.is_market_open(time) {
+            return;
+        }
+        if !self.approve(ticker, -shares, ask, time) {
+            return;
+        }
+
+        if self.broker.short_order(ticker, shares, ask, time).is_some() {
+            self.risk.record_order(time);
+            let pos = Position::open(ticker, -shares, ask, time);
+            self.positions.push(pos);
+            self.exits.open(ticker, ask, true);
+        }
+    }
+
+    // Sends a strategy's order decided on the candle at time, whose close is
+    // price. It fills at that close or, with FillAt::NextOpen, at the open of
+    // the ticker's next candle. Shares are only used to open a position.
+    pub fn send_order(
+        &mut self,
+        ticker: &'a String,
+        signal: Signal,
+        shares: i32,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) {
+        self.guard_order(time);
+        match (signal, self.fill_at) {
+            // stops and targets already rest at the broker
+            (Signal::CloseAt(_), _) | (_, FillAt::Close) => {
+                self.fill_order(ticker, signal, shares, price, time)
+            }
+            (_, FillAt::NextOpen) => self.pending.push(PendingOrder {
+                ticker,
+                signal,
+                shares,
+                sent: time,
+            }),
+        }
+    }
+
+    // Fills an order at price. Orders that no longer make sense, e.g. opening
+    // a second position, are dropped.
+    pub fn fill_order(
+        &mut self,
+        ticker: &'a String,
+        signal: Signal,
+        shares: i32,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) {
+        let open = self.position_for(ticker).is_some();
+        match signal {
+            Signal::Buy if !open => self.open_position(ticker, price, shares, time),
+            Signal::Short if !open => self.open_short_position(ticker, price, shares, time),
+            Signal::Close if open => self.close_position(ticker, price, time),
+            Signal::CloseAt(stop) if open => self.close_position(ticker, stop, time),
+            _ => {}
+        }
+    }
+
+    // Whether ticker has an open position or an order waiting to open one.
+    pub fn has_position_or_order(&self, ticker: &str) -> bool {
+        self.position_for(ticker).is_some()
+            || self.pending.iter().any(|order| order.ticker == ticker)
+    }
+
+    // Fills the ticker's waiting orders at the candle's open, before the candle
+    // reaches the exits.
+    fn fill_pending(&mut self, ticker: &str, candle: &Candle) {
+        let (due, waiting) = self.pending.drain(..).partition(|order: &PendingOrder| {
+            order.ticker == ticker && candle.datetime > order.sent
+        });
+        self.pending = waiting;
+        for order in due {
+            self.fill_order(
+                order.ticker,
+                order.signal,
+                order.shares,
+                candle.open,
+                candle.datetime,
+            );
+        }
+    }
+
+    // Checks an order to open a position against the risk limits and the
+    // pattern day trader rule.
+    fn approve(
+        &mut self,
+        ticker: &str,
+        shares: i32,
+        price: f64,
+        time: clock::LocalDateTime,
+    ) -> bool {
+        let equity = self.equity(time);
+        let order = Order {
+            ticker,
+            shares,
+            price,
+            time,
+        };
+        let last_price = self.last_close(ticker);
+        self.risk
+            .approve(&order, &self.positions, equity, last_price)
+            && self.day_trades.allows_open(ticker, time, equity)
+    }
+
+    fn last_close(&self, ticker: &str) -> Option<f64> {
+        self.candles
+            .get(ticker)
+            .and_then(|candles| candles.last())
+            .map(|candle| candle.close)
+    }
+
+    // Realized profit and loss for positions closed on date, plus open
+    // positions marked from their entry to the last close.
+    pub fn daily_profit_and_loss(&self, date: clock::LocalDate) -> f64 {
+        self.positions
+            .iter()
+            .map(|position| {
+                if position.open {
+                    self.last_close(position.ticker)
+                        .map(|close| (close - position.bid) * position.shares as f64)
+                        .unwrap_or(0.0)
+                } else if position
+                    .closes
+                    .iter()
+                    .any(|close| close.time.date() == date)
+                {
+                    position.total_return()
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+
+    // Closes every open position at its last close.
+    pub fn flatten(&mut self, time: clock::LocalDateTime) {
+        let open: Vec<(String, f64)> = self
+            .positions
+            .iter()
+            .filter(|position| position.open)
+            .map(|position| {
+                let price = self.last_close(position.ticker).unwrap_or(position.bid);
+                (position.ticker.to_string(), price)
+            })
+            .collect();
+        for (ticker, price) in open {
+            self.close_position(&ticker, price, time);
+        }
+    }
+
+    pub fn current_position(&self) -> Option<&Position<'_>> {
         self.positions.last()
     }
 
-    pub fn close_position(&mut self, ticker: &String, ask: f64, time: clock::LocalDateTime) {
-        let mut position = self.positions.pop().unwrap();
-        self.broker.sell_order(ticker, position.shares, ask, time);
-        position.close(ask, time);
-        self.positions.push(position);
+    // The open position for a ticker, when several tickers are held at once.
+    pub fn position_for(&self, ticker: &str) -> Option<&Position<'_>> {
+        self.positions
+            .iter()
+            .rev()
+            .find(|position| position.open && position.ticker == ticker)
+    }
+
+    fn position_for_mut(&mut self, ticker: &str) -> Option<&mut Position<'a>> {
+        self.positions
+            .iter_mut()
+            .rev()
+            .find(|position| position.open && position.ticker == ticker)
+    }
+
+    pub fn close_position(&mut self, ticker: &str, ask: f64, time: clock::LocalDateTime) {
+        self.guard_order(time);
+        let equity = self.equity(time);
+        let broker = &mut self.broker;
+        let open_position = self
+            .positions
+            .iter_mut()
+            .rev()
+            .find(|position| position.open && position.ticker == ticker);
+
+        if let Some(position) = open_position {
+            if position.is_short() {
+                broker.cover_order(ticker, -position.shares, ask, time);
+            } else {
+                broker.sell_order(ticker, position.shares, ask, time);
+            }
+            position.close(ask, time);
+            if position.time.date() == time.date() {
+                self.day_trades.record(time, equity);
+            }
+            self.exits.cancel(ticker);
+            self.risk.record_order(time);
+        }
     }
 
     pub fn is_position_open(&self) -> bool {
@@ -115,9 +633,137 @@ where
         }
     }
 
-    pub fn close_position_for_day(&mut self, ticker: &String, candle: &Candle) {
-        let close_time = clock::Time::from_hms(15, 55, 0);
-        if self.is_position_open() && candle.datetime.time() >= close_time {
+    // Moves the account's clock up to time. Orders stamped after the latest
+    // time are trading on candles the account hasn't seen.
+    pub fn advance(&mut self, time: clock::LocalDateTime) {
+        self.now = self.now.max(Some(time));
+    }
+
+    fn guard_order(&self, time: clock::LocalDateTime) {
+        if self.now.is_some() {
+            guard_look_ahead(self.now, time);
+        }
+    }
+
+    // Called before the strategy sees each candle. Fills orders waiting for its
+    // open, keeps recent candles for sizing and closes the ticker's position
+    // when the candle reaches one of its stops or targets.
+    pub fn add_candle(&mut self, ticker: &str, candle: &Candle) {
+        self.advance(candle.datetime);
+        self.fill_pending(ticker, candle);
+        let candles = self.candles.entry(ticker.to_string()).or_default();
+        candles.push(candle.clone());
+        if candles.len() > MAX_RECENT_CANDLES {
+            candles.remove(0);
+        }
+
+        if let Holding::Swing(config) = self.holding {
+            self.close_on_suspect_gap(ticker, candle, config);
+        }
+        self.broker.mark(ticker, candle.close, candle.datetime);
+        let exit = self.exits.update(ticker, candle);
+        // fills at the candle's open are already in the position when it gets here
+        if let Some(position) = self.position_for_mut(ticker) {
+            if position.time <= candle.datetime {
+                match exit {
+                    Some(price) => position.track_excursion_to_exit(candle, price),
+                    None => position.track_excursion(candle),
+                }
+            }
+        }
+        if let Some(price) = exit {
+            self.close_position(ticker, price, candle.datetime);
+        }
+
+        if self.broker.margin_call() {
+            self.margin_calls.push(candle.datetime);
+            self.flatten(candle.datetime);
+        }
+
+        // the kill switch: once the day's loss reaches the limit nothing else trades
+        let profit_and_loss = self.daily_profit_and_loss(candle.datetime.date());
+        if self.risk.daily_loss_breached(profit_and_loss) {
+            self.flatten(candle.datetime);
+            self.risk.halt(candle.datetime, profit_and_loss);
+        }
+    }
+
+    // Called after every candle. Intraday positions are closed within five minutes
+    // of the market close; swing positions are managed across sessions instead.
+    pub fn close_position_for_day(&mut self, ticker: &str, candle: &Candle) {
+        match self.holding {
+            Holding::Intraday => {
+                let close_time = clock::Time::from_hms(15, 55, 0);
+                if self.position_for(ticker).is_some() && candle.datetime.time() >= close_time {
+                    self.close_position(ticker, candle.close, candle.datetime);
+                }
+            }
+            Holding::Swing(config) => self.manage_swing_position(ticker, candle, config),
+        }
+    }
+
+    // Without corporate action data a split looks like a huge overnight gap.
+    // Rather than stop out a held position at a price it never traded at, it's
+    // closed at the last close before the gap and the gap is recorded. Each
+    // candle is only considered once.
+    fn close_on_suspect_gap(&mut self, ticker: &str, candle: &Candle, config: SwingConfig) {
+        let previous = self
+            .last_closes
+            .insert(ticker.to_string(), (candle.datetime, candle.close));
+        let close = match previous {
+            Some((time, close)) if time.date() < candle.datetime.date() => close,
+            _ => return,
+        };
+        let percent = (candle.open / close - 1.0) * 100.0;
+        let held =
+            matches!(self.position_for(ticker), Some(position) if position.time < candle.datetime);
+        if held && percent.abs() > config.max_gap_percent {
+            self.close_position(ticker, close, candle.datetime);
+            self.suspect_gaps.push(SuspectGap {
+                ticker: ticker.to_string(),
+                time: candle.datetime,
+                percent,
+                close,
+            });
+        }
+    }
+
+    fn manage_swing_position(&mut self, ticker: &str, candle: &Candle, config: SwingConfig) {
+        self.close_on_suspect_gap(ticker, candle, config);
+        let position = match self.position_for(ticker) {
+            // nothing to manage on the bar the position was opened
+            Some(position) if position.time < candle.datetime => position,
+            _ => return,
+        };
+
+        if let Some(percent) = config.stop_percent {
+            let fill = if position.is_short() {
+                let stop = position.bid * (1.0 + percent / 100.0);
+                if candle.open >= stop {
+                    Some(candle.open)
+                } else if candle.high >= stop {
+                    Some(stop)
+                } else {
+                    None
+                }
+            } else {
+                let stop = position.bid * (1.0 - percent / 100.0);
+                if candle.open <= stop {
+                    Some(candle.open)
+                } else if candle.low <= stop {
+                    Some(stop)
+                } else {
+                    None
+                }
+            };
+            if let Some(price) = fill {
+                self.close_position(ticker, price, candle.datetime);
+                return;
+            }
+        }
+
+        let held = clock::business_days_between(position.time.date(), candle.datetime.date());
+        if held >= config.max_holding_days {
             self.close_position(ticker, candle.close, candle.datetime);
         }
     }
@@ -130,6 +776,9 @@ pub struct Position<'a> {
     pub closes: Vec<Close>,
     pub time: clock::LocalDateTime,
     pub ticker: &'a String,
+    // lowest low and highest high since the entry
+    pub low: f64,
+    pub high: f64,
 }
 
 impl<'a> Position<'a> {
@@ -141,9 +790,45 @@ impl<'a> Position<'a> {
             ticker,
             open: true,
             closes: Vec::new(),
+            low: bid,
+            high: bid,
+        }
+    }
+
+    // Widens the range traded through while open by a candle after the entry.
+    pub fn track_excursion(&mut self, candle: &Candle) {
+        self.low = self.low.min(candle.low);
+        self.high = self.high.max(candle.high);
+    }
+
+    // Like track_excursion for the candle an exit order fills on. Past the fill
+    // the position was already closed, so the candle's range stops there.
+    pub fn track_excursion_to_exit(&mut self, candle: &Candle, exit: f64) {
+        if exit <= candle.open {
+            self.low = self.low.min(candle.low.max(exit));
+            self.high = self.high.max(candle.high);
+        } else {
+            self.low = self.low.min(candle.low);
+            self.high = self.high.max(candle.high.min(exit));
         }
     }
 
+    // Maximum adverse excursion: the worst open profit in dollars, zero or less.
+    pub fn mae(&self) -> f64 {
+        self.excursions().0
+    }
+
+    // Maximum favorable excursion: the best open profit in dollars, zero or more.
+    pub fn mfe(&self) -> f64 {
+        self.excursions().1
+    }
+
+    fn excursions(&self) -> (f64, f64) {
+        let low = (self.low - self.bid) * self.shares as f64;
+        let high = (self.high - self.bid) * self.shares as f64;
+        (low.min(high).min(0.0), low.max(high).max(0.0))
+    }
+
     pub fn close(&mut self, ask: f64, time: clock::LocalDateTime) {
         self.open = false;
         self.closes = vec![Close {
@@ -153,6 +838,11 @@ impl<'a> Position<'a> {
         }];
     }
 
+    pub fn is_short(&self) -> bool {
+        self.shares < 0
+    }
+
+    // Signed shares make this correct for shorts as well as longs.
     pub fn total_return(&self) -> f64 {
         let gross: f64 = self
             .closes
@@ -0,0 +1,337 @@
+use super::{
+    apis::candles::Candle,
+    backtest::{AccountSettings, CAPITAL},
+    clock, config,
+    metrics::{self, Summary},
+    optimizer::{self, Search},
+    strategies::SmaCrossConfig,
+    trading::Holding,
+};
+
+// Rolling windows in trading days. Each window optimizes over in_sample_days
+// and trades the best parameters over the following out_of_sample_days, then
+// moves forward by out_of_sample_days so out-of-sample periods never overlap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowConfig {
+    pub in_sample_days: usize,
+    pub out_of_sample_days: usize,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            in_sample_days: 20,
+            out_of_sample_days: 5,
+        }
+    }
+}
+
+pub struct Window {
+    pub in_sample: Vec<clock::LocalDate>,
+    pub out_of_sample: Vec<clock::LocalDate>,
+    // the best parameters in sample and how they did there
+    pub config: SmaCrossConfig,
+    pub in_sample_summary: Summary,
+    // out-of-sample close times and trade returns, in the order they were closed
+    pub trades: Vec<(clock::LocalDateTime, f64)>,
+}
+
+impl Window {
+    pub fn returns(&self) -> Vec<f64> {
+        self.trades.iter().map(|(_, r)| *r).collect()
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::from_returns(&self.returns())
+    }
+
+    pub fn efficiency(&self) -> Option<f64> {
+        efficiency(
+            self.in_sample_summary.net_profit(),
+            self.in_sample.len(),
+            self.summary().net_profit(),
+            self.out_of_sample.len(),
+        )
+    }
+}
+
+pub struct WalkForward {
+    pub windows: Vec<Window>,
+}
+
+impl WalkForward {
+    // Out-of-sample returns of every window stitched together.
+    pub fn returns(&self) -> Vec<f64> {
+        self.windows.iter().flat_map(Window::returns).collect()
+    }
+
+    // Equity after each stitched out-of-sample trade, at the time it closed.
+    pub fn equity(&self, start: f64) -> Vec<(clock::LocalDateTime, f64)> {
+        let times = self
+            .windows
+            .iter()
+            .flat_map(|window| window.trades.iter().map(|(time, _)| *time));
+        times
+            .zip(
+                metrics::cumulative(start, &self.returns())
+                    .into_iter()
+                    .skip(1),
+            )
+            .collect()
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::from_returns(&self.returns())
+    }
+
+    pub fn efficiency(&self) -> Option<f64> {
+        let in_sample_profit = self
+            .windows
+            .iter()
+            .map(|window| window.in_sample_summary.net_profit())
+            .sum();
+        let in_sample_days = self.windows.iter().map(|w| w.in_sample.len()).sum();
+        let out_of_sample_days = self.windows.iter().map(|w| w.out_of_sample.len()).sum();
+        efficiency(
+            in_sample_profit,
+            in_sample_days,
+            self.summary().net_profit(),
+            out_of_sample_days,
+        )
+    }
+}
+
+// Walk-forward efficiency: out-of-sample profit per day as a fraction of the
+// in-sample profit per day. None when the in-sample run didn't make money.
+pub fn efficiency(
+    in_sample_profit: f64,
+    in_sample_days: usize,
+    out_of_sample_profit: f64,
+    out_of_sample_days: usize,
+) -> Option<f64> {
+    if in_sample_profit <= 0.0 || in_sample_days == 0 || out_of_sample_days == 0 {
+        return None;
+    }
+    let in_sample_rate = in_sample_profit / in_sample_days as f64;
+    Some(out_of_sample_profit / out_of_sample_days as f64 / in_sample_rate)
+}
+
+pub fn walk_forward(
+    candles: &[(String, Vec<Candle>)],
+    configs: &[SmaCrossConfig],
+    settings: &AccountSettings,
+    windows: WindowConfig,
+) -> WalkForward {
+    let mut days: Vec<clock::LocalDate> = candles
+        .iter()
+        .flat_map(|(_, candles)| candles.iter().map(|c| c.datetime.date()))
+        .collect();
+    days.sort();
+    days.dedup();
+
+    let mut results = Vec::new();
+    let mut start = 0;
+    while windows.out_of_sample_days > 0 && start + windows.in_sample_days < days.len() {
+        let split = start + windows.in_sample_days;
+        let end = (split + windows.out_of_sample_days).min(days.len());
+        let in_sample = days[start..split].to_vec();
+        let out_of_sample = days[split..end].to_vec();
+        start += windows.out_of_sample_days;
+
+        let in_sample_candles: Vec<(String, Vec<Candle>)> = candles
+            .iter()
+            .map(|(ticker, candles)| {
+                let (from, to) = between(candles, &in_sample);
+                (ticker.clone(), candles[from..to].to_vec())
+            })
+            .collect();
+        let best = match optimizer::sweep(&in_sample_candles, configs, settings)
+            .into_iter()
+            .next()
+        {
+            Some(best) => best,
+            None => break,
+        };
+
+        // out of sample the history comes from the bars just before the window
+        let mut positions = Vec::new();
+        for (ticker, candles) in candles {
+            let (from, to) = between(candles, &out_of_sample);
+            let warmup = warmup_start(candles, from, best.config.slow_bars, &settings.holding);
+            if let (Some(warmup), true) = (warmup, from < to) {
+                let window = candles[warmup..to].to_vec();
+                if let Some(traded) = optimizer::backtest(
+                    ticker,
+                    window,
+                    best.config,
+                    best.config.slow_bars,
+                    settings,
+                ) {
+                    positions.extend(traded);
+                }
+            }
+        }
+
+        results.push(Window {
+            in_sample,
+            out_of_sample,
+            config: best.config,
+            in_sample_summary: best.summary,
+            trades: metrics::closed_trades(&positions),
+        });
+    }
+    WalkForward { windows: results }
+}
+
+// Indexes of the candles from the first to the last of days.
+fn between(candles: &[Candle], days: &[clock::LocalDate]) -> (usize, usize) {
+    let first = days[0];
+    let last = days[days.len() - 1];
+    (
+        candles.partition_point(|c| c.datetime.date() < first),
+        candles.partition_point(|c| c.datetime.date() <= last),
+    )
+}
+
+// Where history of bars bars ending before candles[start] begins, counting
+// days when swing trading. None without enough candles.
+fn warmup_start(candles: &[Candle], start: usize, bars: usize, holding: &Holding) -> Option<usize> {
+    match holding {
+        Holding::Intraday => start.checked_sub(bars),
+        Holding::Swing(_) => {
+            let mut dates: Vec<clock::LocalDate> =
+                candles[..start].iter().map(|c| c.datetime.date()).collect();
+            dates.dedup();
+            let first = dates[dates.len().checked_sub(bars)?];
+            Some(candles.partition_point(|c| c.datetime.date() < first))
+        }
+    }
+}
+
+pub fn run_walk_forward(
+    tickers: &[String],
+    env: &config::Env,
+    configs: &[SmaCrossConfig],
+    search: Search,
+    settings: &AccountSettings,
+    windows: WindowConfig,
+) {
+    let candles = optimizer::load_candles(tickers, env);
+    println!(
+        "Walk-forward over {} parameter sets from {}, {} days in sample and {} out of sample",
+        configs.len(),
+        search.describe(),
+        windows.in_sample_days,
+        windows.out_of_sample_days
+    );
+    println!(
+        "{:>23} {:>5} {:>5} {:>11} {:>7} {:>11} {:>7}",
+        "Out of sample", "Fast", "Slow", "IS Net", "Trades", "OOS Net", "WFE"
+    );
+
+    let format_efficiency = |efficiency: Option<f64>| match efficiency {
+        Some(efficiency) => format!("{:.2}", efficiency),
+        None => "-".to_string(),
+    };
+    let result = walk_forward(&candles, configs, settings, windows);
+    for window in &result.windows {
+        let summary = window.summary();
+        println!(
+            "{} - {} {:>5} {:>5} {:>11.4} {:>7} {:>11.4} {:>7}",
+            window.out_of_sample[0].format("%Y-%m-%d"),
+            window.out_of_sample[window.out_of_sample.len() - 1].format("%Y-%m-%d"),
+            window.config.fast_bars,
+            window.config.slow_bars,
+            window.in_sample_summary.net_profit(),
+            summary.trades,
+            summary.net_profit(),
+            format_efficiency(window.efficiency()),
+        );
+    }
+
+    let summary = result.summary();
+    println!("Out of sample");
+    println!("Trades: {}", summary.trades);
+    println!("W%: {:.2}%", summary.win_percent());
+    println!("Net: {:.4}", summary.net_profit());
+    println!("Max DD: {:.4}", summary.max_drawdown);
+    println!(
+        "Walk-forward efficiency: {}",
+        format_efficiency(result.efficiency())
+    );
+
+    println!("Out-of-sample equity from ${:.2}", CAPITAL);
+    for (time, equity) in result.equity(CAPITAL) {
+        println!("{} {:>11.4}", time.format("%Y-%m-%d %H:%M"), equity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{efficiency, walk_forward, WindowConfig};
+    use crate::{
+        apis::candles::Candle, backtest::AccountSettings, clock, optimizer, optimizer::Range,
+    };
+
+    #[test]
+    fn efficiency_compares_profit_per_day() {
+        assert_eq!(efficiency(100.0, 20, 20.0, 5), Some(0.8));
+        assert_eq!(efficiency(100.0, 20, -10.0, 5), Some(-0.4));
+        assert_eq!(efficiency(-100.0, 20, 20.0, 5), None);
+    }
+
+    #[test]
+    fn out_of_sample_windows_roll_forward_and_are_stitched() {
+        // an hour before the close, Monday to Friday
+        let mut candles = Vec::new();
+        for day in 0..5 {
+            let start = clock::datetime(2020, 9, 21 + day, 15, 0, 0);
+            for minute in 0..60 {
+                let close = 10.0 + ((minute as f64) / 5.0).sin();
+                let open = close - 0.05 * ((minute % 3) as f64 - 1.0);
+                let time = start + clock::Duration::minutes(minute);
+                candles.push(Candle::new(
+                    open,
+                    close,
+                    close.max(open),
+                    close.min(open),
+                    100,
+                    time,
+                ));
+            }
+        }
+        let data = vec![("ABC".to_string(), candles)];
+        let configs = optimizer::parameter_sets(
+            &Range::parse("2:4").unwrap(),
+            &Range::parse("5:10:5").unwrap(),
+            optimizer::Search::Grid,
+        );
+        let windows = WindowConfig {
+            in_sample_days: 2,
+            out_of_sample_days: 1,
+        };
+
+        let result = walk_forward(&data, &configs, &AccountSettings::default(), windows);
+        assert_eq!(result.windows.len(), 3);
+        let out_of_sample: Vec<_> = result
+            .windows
+            .iter()
+            .flat_map(|window| window.out_of_sample.clone())
+            .collect();
+        assert_eq!(
+            out_of_sample,
+            (23..26)
+                .map(|day| clock::datetime(2020, 9, day, 15, 0, 0).date())
+                .collect::<Vec<_>>()
+        );
+        assert!(result.windows.iter().all(|w| w.in_sample.len() == 2));
+        assert!(result.windows.iter().all(|w| !w.trades.is_empty()));
+
+        let trades: usize = result.windows.iter().map(|w| w.trades.len()).sum();
+        assert_eq!(result.summary().trades, trades);
+        let equity = result.equity(1000.0);
+        assert_eq!(equity.len(), trades);
+        assert!(equity.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(equity[trades - 1].1, 1000.0 + result.summary().net_profit());
+    }
+}